@@ -0,0 +1,175 @@
+//! Build cache that lets `xmlman build` skip files that did not change.
+//!
+//! For every output file, the cache records a hash of what it was
+//! transpiled from (the xml, the [`TranspileOptions`] and the xmlman
+//! version), and a hash of the output itself. A file is up to date when
+//! both hashes still match, so editing or deleting an output also
+//! causes it to be transpiled again.
+
+use crate::TranspileOptions;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Default name of the cache directory, next to `xmlman.toml`.
+pub const CACHE_DIR_NAME: &str = ".xmlman-cache";
+
+const CACHE_FILE_NAME: &str = "build.json";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct CacheEntry {
+    input: String,
+    output: String,
+}
+
+/// [`BuildCache`] is the cache stored in a cache directory.
+/// It can be shared by the threads that transpile files in parallel.
+#[derive(Debug)]
+pub struct BuildCache {
+    dir: PathBuf,
+    /// Entries by the absolute path of the output file.
+    entries: Mutex<BTreeMap<PathBuf, CacheEntry>>,
+}
+
+impl BuildCache {
+    /// Load the cache in `dir`.
+    /// A missing or unreadable cache is treated as an empty one.
+    pub fn load(dir: &Path) -> Self {
+        let entries = fs::read_to_string(dir.join(CACHE_FILE_NAME))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+
+        BuildCache { dir: dir.to_path_buf(), entries: Mutex::new(entries) }
+    }
+
+    /// Whether `out_path` was transpiled from `source` with `options`,
+    /// and has not been changed since.
+    pub fn is_up_to_date(&self, out_path: &Path, source: &str, options: &TranspileOptions) -> bool {
+        let entries = self.entries.lock().unwrap();
+        let Some(entry) = entries.get(&absolute(out_path)) else { return false };
+
+        entry.input == input_hash(source, options)
+            && fs::read_to_string(out_path).is_ok_and(|output| entry.output == hash(&output))
+    }
+
+    /// Record that `output` was written to `out_path`.
+    pub fn record(&self, out_path: &Path, source: &str, options: &TranspileOptions, output: &str) {
+        let entry = CacheEntry { input: input_hash(source, options), output: hash(output) };
+        self.entries.lock().unwrap().insert(absolute(out_path), entry);
+    }
+
+    /// Write the cache back to its directory.
+    pub fn save(&self) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        // The cache is specific to this machine, keep it out of git
+        fs::write(self.dir.join(".gitignore"), "*\n")?;
+
+        let content = serde_json::to_string_pretty(&*self.entries.lock().unwrap())
+            .map_err(io::Error::other)?;
+        fs::write(self.dir.join(CACHE_FILE_NAME), content)
+    }
+}
+
+fn input_hash(source: &str, options: &TranspileOptions) -> String {
+    hash((env!("CARGO_PKG_VERSION"), source, options))
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn hash(value: impl Hash) -> String {
+    let mut hasher = StableHasher(0xcbf2_9ce4_8422_2325);
+    value.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// FNV-1a. Unlike the hasher of the standard library,
+/// it gives the same hash in every build of xmlman.
+struct StableHasher(u64);
+
+impl Hasher for StableHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "<Root/>";
+    const OUTPUT: &str = "enter([])\n";
+
+    /// A cache with a single output, written and recorded in a new directory.
+    fn cached_output(name: &str) -> (BuildCache, PathBuf) {
+        let dir =
+            std::env::temp_dir().join(format!("xmlman-cache-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let out_path = dir.join("bar.rhai");
+        fs::write(&out_path, OUTPUT).unwrap();
+
+        let cache = BuildCache::load(&dir.join(CACHE_DIR_NAME));
+        cache.record(&out_path, SOURCE, &TranspileOptions::default(), OUTPUT);
+        (cache, out_path)
+    }
+
+    #[test]
+    fn unchanged_input_is_up_to_date() {
+        let (cache, out_path) = cached_output("unchanged");
+        let options = TranspileOptions::default();
+
+        assert!(cache.is_up_to_date(&out_path, SOURCE, &options));
+        assert!(!cache.is_up_to_date(&out_path, "<Root></Root>", &options));
+
+        // The entries survive a save and a load
+        cache.save().unwrap();
+        let loaded = BuildCache::load(&cache.dir);
+        assert!(loaded.is_up_to_date(&out_path, SOURCE, &options));
+
+        fs::remove_dir_all(out_path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn changed_options_invalidate_the_cache() {
+        let (cache, out_path) = cached_output("options");
+
+        let mut minified = TranspileOptions::default();
+        minified.rhai_style.minify = true;
+        assert!(!cache.is_up_to_date(&out_path, SOURCE, &minified));
+
+        let defines = [("a".to_string(), "b".to_string())].into();
+        let options = TranspileOptions { defines, ..Default::default() };
+        assert!(!cache.is_up_to_date(&out_path, SOURCE, &options));
+
+        fs::remove_dir_all(out_path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn changed_output_invalidates_the_cache() {
+        let (cache, out_path) = cached_output("output");
+        let options = TranspileOptions::default();
+
+        fs::write(&out_path, "enter([]) // edited\n").unwrap();
+        assert!(!cache.is_up_to_date(&out_path, SOURCE, &options));
+
+        fs::remove_file(&out_path).unwrap();
+        assert!(!cache.is_up_to_date(&out_path, SOURCE, &options));
+
+        fs::remove_dir_all(out_path.parent().unwrap()).unwrap();
+    }
+}
@@ -6,29 +6,61 @@ mod enter_check;
 
 use enter_check::check_for_enter;
 
+use crate::error::explain_hint;
 use crate::transpiler::InternalTree;
 use colored::Colorize;
-use log::error;
 
-pub fn run_all_checks(tree: &InternalTree) -> Result<(), ()> {
+/// [`CheckError`] is a failed check.
+/// The code is documented in `logs/CHECK_ERRORS.md`
+/// and can be explained with `xmlman explain`.
+#[derive(Debug, Clone)]
+pub struct CheckError {
+    pub code: &'static str,
+    pub message: &'static str,
+}
+
+/// A check returns the reason it failed as the error.
+type Check = fn(&InternalTree) -> Result<(), &'static str>;
+
+/// Run every check whose code is not in `disabled`.
+pub fn run_all_checks(tree: &InternalTree, disabled: &[String]) -> Result<(), Vec<CheckError>> {
     // CE == Check Error
+    let checks: [(&str, Check); 1] = [("CE01", check_for_enter)];
 
-    let mut checks_failed = 0;
+    let mut failed = Vec::new();
 
-    if let Err(e) = check_for_enter(tree) {
-        error!("{} {}", "[CE01]".cyan(), e);
-        checks_failed += 1
-    }
+    for (code, check) in checks {
+        if disabled.iter().any(|d| d.eq_ignore_ascii_case(code)) {
+            continue;
+        }
 
-    if checks_failed > 0 {
-        if checks_failed == 1 {
-            error!("1 check failed. Exiting.");
-        } else {
-            error!("{} checks failed. Exiting.", checks_failed);
+        if let Err(e) = check(tree) {
+            failed.push(CheckError { code, message: e });
         }
+    }
 
-        Err(())
-    } else {
-        Ok(())
+    match failed.is_empty() {
+        true => Ok(()),
+        false => Err(failed),
     }
 }
+
+/// The error lines that failed checks are reported with.
+/// The checks don't log anything themselves, so that files that
+/// are checked in parallel can still be reported in order.
+pub fn report_lines(failed: &[CheckError]) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    for check_error in failed {
+        lines.push(format!("{} {}", format!("[{}]", check_error.code).cyan(), check_error.message));
+        lines.push(format!("{} {}", "help:".cyan(), explain_hint(check_error.code)));
+    }
+
+    match failed.len() {
+        0 => {}
+        1 => lines.push("1 check failed. Exiting.".to_string()),
+        n => lines.push(format!("{} checks failed. Exiting.", n)),
+    }
+
+    lines
+}
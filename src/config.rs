@@ -0,0 +1,153 @@
+//! Project configuration read from `xmlman.toml`.
+//!
+//! The file is discovered by walking up from the current directory,
+//! so xmlman can be run from anywhere inside of a project. All paths
+//! in the file are relative to the directory that contains it.
+
+use crate::cache::CACHE_DIR_NAME;
+use crate::error::DiagInfo;
+use crate::formatter::FmtOptions;
+use crate::{Indent, RhaiStyle, Target, TranspileOptions};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+pub const CONFIG_FILE_NAME: &str = "xmlman.toml";
+
+/// [`ProjectConfig`] is the contents of an `xmlman.toml` file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProjectConfig {
+    pub build: BuildConfig,
+    pub fmt: FmtConfig,
+    pub checks: ChecksConfig,
+    pub rhai: RhaiConfig,
+    /// Custom widgets, from the xml element name
+    /// to the rhai function that creates the widget.
+    pub widgets: BTreeMap<String, String>,
+    /// Values that replace `@define(NAME)` in attributes.
+    pub defines: BTreeMap<String, String>,
+
+    /// Directory that contains the config file.
+    #[serde(skip)]
+    pub dir: PathBuf,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BuildConfig {
+    /// Files, directories and glob patterns to transpile.
+    pub inputs: Vec<String>,
+    /// Directory to write the transpiled files to.
+    pub out: Option<PathBuf>,
+    /// Directory to keep the build cache in.
+    pub cache_dir: Option<PathBuf>,
+    /// The backend that generates the transpiled files.
+    pub target: Target,
+    /// Generate a module for each window, imported by the transpiled file.
+    pub split_windows: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FmtConfig {
+    /// Number of spaces per indentation level.
+    pub indent: Option<usize>,
+    /// Put each attribute on its own line when a start tag is too long.
+    pub wrap_attributes: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RhaiConfig {
+    /// Number of spaces per indentation level, or `"tab"`.
+    pub indent: Option<Indent>,
+    /// Maximum line width before maps are expanded.
+    pub max_width: Option<usize>,
+    /// Put a comma after the last entry of expanded maps and arrays.
+    pub trailing_commas: Option<bool>,
+    /// Number of blank lines between functions.
+    pub blank_lines: Option<usize>,
+    /// Generate Rhai without optional whitespace or long function names.
+    pub minify: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChecksConfig {
+    /// Codes of the checks that should not run.
+    pub disabled: Vec<String>,
+}
+
+/// Find the closest `xmlman.toml` in `start` or any of its parents.
+pub fn find_config(start: &Path) -> Option<PathBuf> {
+    start.ancestors().map(|dir| dir.join(CONFIG_FILE_NAME)).find(|path| path.is_file())
+}
+
+impl ProjectConfig {
+    /// Parse the contents of the config file at `path`.
+    pub fn parse(path: &Path, content: &str) -> Result<Self, DiagInfo<'static>> {
+        let mut config: ProjectConfig = toml::from_str(content).map_err(|e| DiagInfo {
+            code: "PE03",
+            message: format!("Invalid project configuration: {}", e.message()),
+            label: Some("here"),
+            note: None,
+            span: e.span(),
+        })?;
+
+        config.dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        Ok(config)
+    }
+
+    /// The input patterns, relative to the current directory.
+    pub fn input_patterns(&self) -> Vec<String> {
+        self.build.inputs.iter().map(|i| self.dir.join(i).to_string_lossy().into_owned()).collect()
+    }
+
+    /// Options to transpile the project's files with.
+    pub fn transpile_options(&self) -> TranspileOptions {
+        TranspileOptions {
+            out: self.out_dir(),
+            widgets: self.widgets.clone(),
+            defines: self.defines.clone(),
+            disabled_checks: self.checks.disabled.clone(),
+            target: self.build.target,
+            rhai_style: self.rhai_style(),
+            split_windows: self.build.split_windows,
+        }
+    }
+
+    /// How the project's Rhai code is laid out.
+    pub fn rhai_style(&self) -> RhaiStyle {
+        let default = RhaiStyle::default();
+
+        RhaiStyle {
+            indent: self.rhai.indent.unwrap_or(default.indent),
+            max_width: self.rhai.max_width.or(default.max_width),
+            trailing_commas: self.rhai.trailing_commas.unwrap_or(default.trailing_commas),
+            blank_lines: self.rhai.blank_lines.unwrap_or(default.blank_lines),
+            minify: self.rhai.minify.unwrap_or(default.minify),
+        }
+    }
+
+    /// Options to format the project's files with.
+    pub fn fmt_options(&self) -> FmtOptions {
+        let default = FmtOptions::default();
+
+        FmtOptions {
+            indent_width: self.fmt.indent.unwrap_or(default.indent_width),
+            wrap_attributes: self.fmt.wrap_attributes.unwrap_or(default.wrap_attributes),
+            ..default
+        }
+    }
+
+    /// Build cache directory, relative to the current directory.
+    pub fn cache_dir(&self) -> PathBuf {
+        self.dir.join(self.build.cache_dir.as_deref().unwrap_or(Path::new(CACHE_DIR_NAME)))
+    }
+
+    /// Output directory, relative to the current directory.
+    pub fn out_dir(&self) -> Option<PathBuf> {
+        self.build.out.as_ref().map(|out| self.dir.join(out))
+    }
+}
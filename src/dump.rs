@@ -0,0 +1,140 @@
+//! JSON dumps of the [`Ast`] and the [`InternalTree`], for `xmlman ast`.
+//!
+//! Both dumps are a single object with the `version` of the format, the
+//! `stage` that was dumped, the `file` and the `root` node. The format is
+//! documented in `docs/src/usage.md`, and `version` is bumped whenever a
+//! change to it could break tools that read it.
+
+use crate::FileInfo;
+use crate::error::{JsonSpan, json_span};
+use crate::parser::{Ast, Node};
+use crate::transpiler::{Attr, InternalTree, Span};
+use serde::Serialize;
+
+/// Version of the JSON format.
+pub const FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize)]
+struct Dump<'a, T> {
+    version: u32,
+    stage: &'static str,
+    file: &'a str,
+    root: T,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonAttr<'a> {
+    key: &'a str,
+    value: &'a str,
+}
+
+/// A [`Node`] of the parsed xml.
+#[derive(Debug, Serialize)]
+struct JsonNode<'a> {
+    name: &'a str,
+    attributes: Vec<JsonAttr<'a>>,
+    span: Option<JsonSpan>,
+    children: Vec<JsonNode<'a>>,
+}
+
+/// A node of the [`InternalTree`].
+#[derive(Debug, Serialize)]
+struct JsonTree<'a> {
+    /// Name of the [`InternalTree`] variant.
+    kind: &'static str,
+    /// The name of a window.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<&'a str>,
+    /// The variable of a poll or listen.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    var: Option<&'a str>,
+    /// The rhai function of a custom widget.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    function: Option<&'a str>,
+    attrs: Vec<JsonAttr<'a>>,
+    span: Option<JsonSpan>,
+    /// The widgets inside of the node. A window has its widget here.
+    children: Vec<JsonTree<'a>>,
+}
+
+/// The parsed xml of `file_info` as pretty printed JSON.
+pub fn ast_json(file_info: &FileInfo, ast: &Ast) -> String {
+    let root = node_json(file_info.script, ast, ast.root());
+    to_json(Dump { version: FORMAT_VERSION, stage: "parse", file: file_info.file_path, root })
+}
+
+/// The internal tree of `file_info` as pretty printed JSON.
+pub fn tree_json(file_info: &FileInfo, tree: &InternalTree) -> String {
+    let root = tree_node_json(file_info.script, tree);
+    to_json(Dump { version: FORMAT_VERSION, stage: "tree", file: file_info.file_path, root })
+}
+
+fn to_json(dump: Dump<impl Serialize>) -> String {
+    serde_json::to_string_pretty(&dump).expect("Failed to serialize the dump")
+}
+
+fn node_json<'a>(script: &str, ast: &'a Ast, node: &'a Node) -> JsonNode<'a> {
+    JsonNode {
+        name: &node.name,
+        attributes: node.attributes.iter().map(|(key, value)| JsonAttr { key, value }).collect(),
+        span: node.span.map(|span| json_span(script, span.range())),
+        children: ast.children(node).map(|child| node_json(script, ast, child)).collect(),
+    }
+}
+
+fn tree_node_json<'a>(script: &str, tree: &'a InternalTree) -> JsonTree<'a> {
+    let (mut name, mut var, mut function) = (None, None, None);
+
+    let (kind, attrs, children, span): (_, &[Attr], &[InternalTree], &Span) = match tree {
+        InternalTree::Enter { children, span } => ("Enter", &[], children, span),
+        InternalTree::DefWindow { name: window, attrs, node, span } => {
+            name = Some(window.as_ref());
+            ("DefWindow", attrs, std::slice::from_ref(node.as_ref()), span)
+        }
+        InternalTree::Poll { var: poll, attrs, span } => {
+            var = Some(poll.as_ref());
+            ("Poll", attrs, &[], span)
+        }
+        InternalTree::Listen { var: listen, attrs, span } => {
+            var = Some(listen.as_ref());
+            ("Listen", attrs, &[], span)
+        }
+        InternalTree::Custom { function: custom, attrs, children, span } => {
+            function = Some(custom.as_ref());
+            ("Custom", attrs, children, span)
+        }
+        InternalTree::Box { attrs, children, span } => ("Box", attrs, children, span),
+        InternalTree::CenterBox { attrs, children, span } => ("CenterBox", attrs, children, span),
+        InternalTree::Expander { attrs, children, span } => ("Expander", attrs, children, span),
+        InternalTree::Revealer { attrs, children, span } => ("Revealer", attrs, children, span),
+        InternalTree::Scroll { attrs, children, span } => ("Scroll", attrs, children, span),
+        InternalTree::OverLay { attrs, children, span } => ("OverLay", attrs, children, span),
+        InternalTree::Stack { attrs, children, span } => ("Stack", attrs, children, span),
+        InternalTree::EventBox { attrs, children, span } => ("EventBox", attrs, children, span),
+        InternalTree::ToolTip { attrs, children, span } => ("ToolTip", attrs, children, span),
+        InternalTree::Label { attrs, span } => ("Label", attrs, &[], span),
+        InternalTree::Button { attrs, span } => ("Button", attrs, &[], span),
+        InternalTree::Image { attrs, span } => ("Image", attrs, &[], span),
+        InternalTree::Input { attrs, span } => ("Input", attrs, &[], span),
+        InternalTree::Progress { attrs, span } => ("Progress", attrs, &[], span),
+        InternalTree::ComboBoxText { attrs, span } => ("ComboBoxText", attrs, &[], span),
+        InternalTree::Slider { attrs, span } => ("Slider", attrs, &[], span),
+        InternalTree::Checkbox { attrs, span } => ("Checkbox", attrs, &[], span),
+        InternalTree::Calendar { attrs, span } => ("Calendar", attrs, &[], span),
+        InternalTree::ColorButton { attrs, span } => ("ColorButton", attrs, &[], span),
+        InternalTree::ColorChooser { attrs, span } => ("ColorChooser", attrs, &[], span),
+        InternalTree::CircularProgress { attrs, span } => ("CircularProgress", attrs, &[], span),
+        InternalTree::Graph { attrs, span } => ("Graph", attrs, &[], span),
+        InternalTree::Transform { attrs, span } => ("Transform", attrs, &[], span),
+    };
+
+    JsonTree {
+        kind,
+        name,
+        var,
+        function,
+        attrs: attrs.iter().map(|a| JsonAttr { key: &a.key, value: &a.value }).collect(),
+        span: span.to_range().map(|range| json_span(script, range)),
+        children: children.iter().map(|child| tree_node_json(script, child)).collect(),
+    }
+}
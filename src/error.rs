@@ -1,11 +1,19 @@
 //! Diagnostic errors using _ariadne_ crate.
+//!
+//! Diagnostics can also be printed as JSON, one object per
+//! line, for editors and other tools to read.
 
 use ariadne::{Color, ColorGenerator, Fmt, Label, Report, ReportKind, Source};
+use serde::Serialize;
+use std::io::{self, Write};
 use std::ops::Range;
 
 /// [`DiagInfo`] struct is used to hold the contents used
 /// to generate an pretty diagnostic error with ariadne.
+#[derive(Debug)]
 pub struct DiagInfo<'a> {
+    /// Error code that can be explained with `xmlman explain`.
+    pub code: &'static str,
     pub message: String,
     pub label: Option<&'a str>,
     pub note: Option<&'a str>,
@@ -13,6 +21,16 @@ pub struct DiagInfo<'a> {
 }
 
 pub fn print_diag_error(file_path: Option<&str>, source_code: &str, info: DiagInfo) {
+    write_diag_error(file_path, source_code, info, io::stdout()).unwrap();
+}
+
+/// Write the report that [`print_diag_error`] prints to `w` instead.
+pub fn write_diag_error(
+    file_path: Option<&str>,
+    source_code: &str,
+    info: DiagInfo,
+    w: impl Write,
+) -> io::Result<()> {
     let file_id = file_path.unwrap_or("<code>");
     let span = match info.span {
         Some(s) => s,
@@ -24,8 +42,10 @@ pub fn print_diag_error(file_path: Option<&str>, source_code: &str, info: DiagIn
     let a = colors.next();
     let out = Color::Fixed(81);
 
-    let mut report =
-        Report::build(ReportKind::Error, (file_id, span.clone())).with_message(info.message);
+    let mut report = Report::build(ReportKind::Error, (file_id, span.clone()))
+        .with_code(info.code)
+        .with_message(info.message)
+        .with_help(explain_hint(info.code));
 
     if let Some(label) = info.label {
         report = report.with_label(Label::new((file_id, span)).with_message(label).with_color(a))
@@ -36,5 +56,92 @@ pub fn print_diag_error(file_path: Option<&str>, source_code: &str, info: DiagIn
     };
 
     // print error
-    report.finish().print((file_id, Source::from(source_code))).unwrap();
+    report.finish().write((file_id, Source::from(source_code)), w)
+}
+
+/// Hint pointing to the long form explanation of an error code.
+pub fn explain_hint(code: &str) -> String {
+    format!("For more information about this error, try `xmlman explain {}`.", code)
+}
+
+/// A [`DiagInfo`] as it is printed by [`print_diag_json`].
+#[derive(Debug, Serialize)]
+struct JsonDiag<'a> {
+    code: Option<&'a str>,
+    severity: &'a str,
+    message: &'a str,
+    label: Option<&'a str>,
+    note: Option<&'a str>,
+    file: Option<&'a str>,
+    span: Option<JsonSpan>,
+}
+
+/// Byte offsets of a span, along with the 1-based
+/// line and column (in characters) of both ends.
+#[derive(Debug, Serialize)]
+pub(crate) struct JsonSpan {
+    start: usize,
+    end: usize,
+    line: usize,
+    column: usize,
+    end_line: usize,
+    end_column: usize,
+}
+
+/// Print a diagnostic as a single line of JSON to stdout.
+pub fn print_diag_json(file_path: Option<&str>, source_code: &str, info: DiagInfo) {
+    println!("{}", diag_json(file_path, source_code, info));
+}
+
+/// The JSON object that [`print_diag_json`] prints.
+pub fn diag_json(file_path: Option<&str>, source_code: &str, info: DiagInfo) -> String {
+    let span = info.span.map(|span| json_span(source_code, span));
+
+    let diag = JsonDiag {
+        code: Some(info.code),
+        severity: "error",
+        message: &info.message,
+        label: info.label,
+        note: info.note,
+        file: file_path,
+        span,
+    };
+
+    serde_json::to_string(&diag).expect("Failed to serialize diagnostic")
+}
+
+/// A JSON object like the ones of [`diag_json`], for an error without a
+/// code or a span, like a file that could not be read.
+pub fn error_json(file_path: Option<&str>, message: &str) -> String {
+    let diag = JsonDiag {
+        code: None,
+        severity: "error",
+        message,
+        label: None,
+        note: None,
+        file: file_path,
+        span: None,
+    };
+
+    serde_json::to_string(&diag).expect("Failed to serialize error")
+}
+
+/// The [`JsonSpan`] of the byte range `span` in `source`.
+pub(crate) fn json_span(source: &str, span: Range<usize>) -> JsonSpan {
+    let (line, column) = line_column(source, span.start);
+    let (end_line, end_column) = line_column(source, span.end);
+    JsonSpan { start: span.start, end: span.end, line, column, end_line, end_column }
+}
+
+/// 1-based line and column of the byte `offset` in `source`.
+fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let mut offset = offset.min(source.len());
+    while !source.is_char_boundary(offset) {
+        offset -= 1;
+    }
+
+    let before = &source[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+
+    (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
 }
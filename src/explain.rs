@@ -0,0 +1,28 @@
+//! Long form explanations of error codes.
+//!
+//! The explanations are the error lists in `logs/`, embedded into the
+//! binary so that `xmlman explain` works without access to the repository.
+
+const ERROR_LISTS: [&str; 3] = [
+    include_str!("../logs/CHECK_ERRORS.md"),
+    include_str!("../logs/TRANSPILE_ERRORS.md"),
+    include_str!("../logs/IMPORT_ERRORS.md"),
+];
+
+/// Get the explanation of an error code such as `CE01`.
+/// Returns `None` if the error code does not exist.
+pub fn explanation(code: &str) -> Option<&'static str> {
+    let heading = format!("## [{}]", code.trim().to_uppercase());
+
+    ERROR_LISTS.iter().find_map(|list| {
+        let section = &list[list.find(&heading)?..];
+
+        // A section ends where the next error code starts
+        let end = section[heading.len()..]
+            .find("\n## ")
+            .map(|i| i + heading.len())
+            .unwrap_or(section.len());
+
+        Some(section[..end].trim_end())
+    })
+}
@@ -0,0 +1,306 @@
+//! XML formatter used by `xmlman fmt`.
+//!
+//! The script is first validated with [`parse_xml`] so that formatting
+//! errors get the same diagnostics as transpiling. It is then tokenized
+//! again with _xmlparser_ to build a small layout tree that keeps the
+//! comments, text, CDATA and declarations that the AST drops.
+
+use crate::FileInfo;
+use crate::error::DiagInfo;
+use crate::parser::parse_xml;
+use xmlparser::{ElementEnd, StrSpan, Token, Tokenizer};
+
+/// Options that control how xml is formatted.
+#[derive(Debug, Clone)]
+pub struct FmtOptions {
+    /// Number of spaces per indentation level.
+    pub indent_width: usize,
+    /// Maximum line width before attributes are wrapped.
+    pub max_width: usize,
+    /// Put each attribute on its own line when the
+    /// start tag is longer than `max_width`.
+    pub wrap_attributes: bool,
+}
+
+impl Default for FmtOptions {
+    fn default() -> Self {
+        FmtOptions { indent_width: 4, max_width: 100, wrap_attributes: false }
+    }
+}
+
+struct Element<'a> {
+    name: &'a str,
+    attrs: Vec<String>,
+    children: Vec<FmtNode<'a>>,
+    self_closing: bool,
+}
+
+enum FmtNode<'a> {
+    Element(Element<'a>),
+    /// Text content, trimmed of surrounding whitespace.
+    Text(&'a str),
+    /// Comments, CDATA, declarations and everything else
+    /// that is written back exactly as it was.
+    Raw(&'a str),
+    /// A blank line the author put between two nodes.
+    BlankLine,
+}
+
+/// Format the xml script in `file_info`.
+pub fn format_xml<'a>(
+    file_info: &FileInfo<'a>,
+    options: &FmtOptions,
+) -> Result<String, DiagInfo<'a>> {
+    parse_xml(file_info)?;
+
+    let nodes = build_nodes(file_info.script);
+
+    let mut out = String::new();
+    write_nodes(&nodes, 0, options, &mut out);
+    Ok(out)
+}
+
+fn build_nodes(script: &str) -> Vec<FmtNode<'_>> {
+    let mut top_level = Vec::new();
+    let mut stack: Vec<Element> = Vec::new();
+
+    fn push<'a>(stack: &mut [Element<'a>], top_level: &mut Vec<FmtNode<'a>>, node: FmtNode<'a>) {
+        match stack.last_mut() {
+            Some(parent) => parent.children.push(node),
+            None => top_level.push(node),
+        }
+    }
+
+    // Where the previous top-level node ended. Whitespace outside of the root
+    // element is not tokenized, so blank lines there are found through it.
+    let mut top_level_end = 0;
+
+    // The script was validated already, so there are no errors to handle
+    for token in Tokenizer::from(script).filter_map(|t| t.ok()) {
+        if stack.is_empty() {
+            let span = token_span(&token);
+            if script[top_level_end..span.start()].matches('\n').count() > 1 {
+                top_level.push(FmtNode::BlankLine);
+            }
+            top_level_end = span.end();
+        }
+
+        match token {
+            Token::ElementStart { span, .. } => stack.push(Element {
+                name: &span.as_str()[1..],
+                attrs: Vec::new(),
+                children: Vec::new(),
+                self_closing: false,
+            }),
+            Token::Attribute { prefix, local, value, .. } => {
+                let name = match prefix.is_empty() {
+                    true => local.as_str(),
+                    false => &script[prefix.start()..local.end()],
+                };
+                // Keep the original quote, as the value may contain the other one
+                let quote = &script[value.start() - 1..value.start()];
+
+                if let Some(element) = stack.last_mut() {
+                    element.attrs.push(format!("{}={}{}{}", name, quote, value, quote));
+                }
+            }
+            Token::ElementEnd { end: ElementEnd::Open, .. } => {}
+            Token::ElementEnd { end, span } => {
+                if let Some(mut element) = stack.pop() {
+                    element.self_closing = matches!(end, ElementEnd::Empty);
+                    push(&mut stack, &mut top_level, FmtNode::Element(element));
+                }
+
+                top_level_end = span.end();
+            }
+            Token::Text { text } => {
+                let trimmed = text.as_str().trim();
+
+                let node = if !trimmed.is_empty() {
+                    FmtNode::Text(trimmed)
+                } else if text.as_str().matches('\n').count() > 1 {
+                    FmtNode::BlankLine
+                } else {
+                    continue;
+                };
+
+                push(&mut stack, &mut top_level, node);
+            }
+            Token::Declaration { span, .. }
+            | Token::ProcessingInstruction { span, .. }
+            | Token::Comment { span, .. }
+            | Token::DtdStart { span, .. }
+            | Token::EmptyDtd { span, .. }
+            | Token::EntityDeclaration { span, .. }
+            | Token::DtdEnd { span }
+            | Token::Cdata { span, .. } => {
+                push(&mut stack, &mut top_level, FmtNode::Raw(span.as_str()));
+            }
+        }
+    }
+
+    top_level
+}
+
+fn token_span<'a>(token: &Token<'a>) -> StrSpan<'a> {
+    match *token {
+        Token::Declaration { span, .. }
+        | Token::ProcessingInstruction { span, .. }
+        | Token::Comment { span, .. }
+        | Token::DtdStart { span, .. }
+        | Token::EmptyDtd { span, .. }
+        | Token::EntityDeclaration { span, .. }
+        | Token::DtdEnd { span }
+        | Token::ElementStart { span, .. }
+        | Token::Attribute { span, .. }
+        | Token::ElementEnd { span, .. }
+        | Token::Cdata { span, .. } => span,
+        Token::Text { text } => text,
+    }
+}
+
+/// Drop the blank lines at the start and end of `nodes`.
+fn trim_blank_lines<'n, 'a>(nodes: &'n [FmtNode<'a>]) -> &'n [FmtNode<'a>] {
+    let is_content = |n: &FmtNode| !matches!(n, FmtNode::BlankLine);
+
+    match (nodes.iter().position(is_content), nodes.iter().rposition(is_content)) {
+        (Some(start), Some(end)) => &nodes[start..=end],
+        _ => &[],
+    }
+}
+
+fn write_nodes(nodes: &[FmtNode], depth: usize, options: &FmtOptions, out: &mut String) {
+    let indent = " ".repeat(depth * options.indent_width);
+    let mut last_was_blank = false;
+
+    for node in trim_blank_lines(nodes) {
+        match node {
+            // Collapse multiple blank lines into one
+            FmtNode::BlankLine if last_was_blank => continue,
+            FmtNode::BlankLine => out.push('\n'),
+            FmtNode::Text(text) | FmtNode::Raw(text) => {
+                out.push_str(&format!("{}{}\n", indent, text));
+            }
+            FmtNode::Element(element) => write_element(element, depth, options, out),
+        }
+
+        last_was_blank = matches!(node, FmtNode::BlankLine);
+    }
+}
+
+fn write_element(element: &Element, depth: usize, options: &FmtOptions, out: &mut String) {
+    let indent = " ".repeat(depth * options.indent_width);
+    let close = if element.self_closing { "/>" } else { ">" };
+
+    let attrs: String = element.attrs.iter().map(|a| format!(" {}", a)).collect();
+    let start_tag = format!("{}<{}{}{}", indent, element.name, attrs, close);
+
+    if options.wrap_attributes
+        && element.attrs.len() > 1
+        && start_tag.chars().count() > options.max_width
+    {
+        let attr_indent = " ".repeat((depth + 1) * options.indent_width);
+        out.push_str(&format!("{}<{}", indent, element.name));
+        for attr in &element.attrs {
+            out.push_str(&format!("\n{}{}", attr_indent, attr));
+        }
+        out.push_str(close);
+    } else {
+        out.push_str(&start_tag);
+    }
+
+    if element.self_closing {
+        out.push('\n');
+        return;
+    }
+
+    let end_tag = format!("</{}>", element.name);
+
+    match trim_blank_lines(&element.children) {
+        [] => out.push_str(&format!("{}\n", end_tag)),
+        // Short text stays on the same line as its element
+        [FmtNode::Text(text)]
+            if !text.contains('\n')
+                && out.rsplit('\n').next().unwrap_or_default().len()
+                    + text.len()
+                    + end_tag.len()
+                    <= options.max_width =>
+        {
+            out.push_str(&format!("{}{}\n", text, end_tag));
+        }
+        children => {
+            out.push('\n');
+            write_nodes(children, depth + 1, options, out);
+            out.push_str(&format!("{}{}\n", indent, end_tag));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(script: &str, options: &FmtOptions) -> String {
+        format_xml(&FileInfo { file_path: "test.xml", script }, options).unwrap()
+    }
+
+    const SCRIPT: &str = concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<Root>\n",
+        "<!--  a   comment <Box/>  -->\n",
+        "      <Box orientation='v' spacing=\"5\">\n",
+        "  <Label text='say \"hi\"'/>\n",
+        "\n\n\n",
+        "<Label>  short text  </Label>\n",
+        "          <Script><![CDATA[ a < b &&  c ]]></Script>\n",
+        "</Box>\n",
+        "</Root>\n",
+    );
+
+    #[test]
+    fn formatting_is_idempotent() {
+        let narrow = FmtOptions { max_width: 20, wrap_attributes: true, ..Default::default() };
+
+        for options in [FmtOptions::default(), narrow] {
+            let formatted = format(SCRIPT, &options);
+            assert_eq!(format(&formatted, &options), formatted);
+        }
+    }
+
+    #[test]
+    fn layout_is_normalized() {
+        let expected = concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<Root>\n",
+            "    <!--  a   comment <Box/>  -->\n",
+            "    <Box orientation='v' spacing=\"5\">\n",
+            "        <Label text='say \"hi\"'/>\n",
+            "\n",
+            "        <Label>short text</Label>\n",
+            "        <Script>\n",
+            "            <![CDATA[ a < b &&  c ]]>\n",
+            "        </Script>\n",
+            "    </Box>\n",
+            "</Root>\n",
+        );
+
+        assert_eq!(format(SCRIPT, &FmtOptions::default()), expected);
+    }
+
+    #[test]
+    fn long_text_gets_its_own_line() {
+        let options = FmtOptions { max_width: 30, ..Default::default() };
+        let script = "<Root><Label>short</Label><Label>a much longer text</Label></Root>";
+
+        let expected = concat!(
+            "<Root>\n",
+            "    <Label>short</Label>\n",
+            "    <Label>\n",
+            "        a much longer text\n",
+            "    </Label>\n",
+            "</Root>\n",
+        );
+
+        assert_eq!(format(script, &options), expected);
+    }
+}
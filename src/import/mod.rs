@@ -0,0 +1,59 @@
+//! Import configs that were not written with xmlman, for `xmlman import`.
+//!
+//! An import converts the config into an [`InternalTree`], the same tree
+//! that xml is transpiled from. The tree is then written back as xml and
+//! formatted like `xmlman fmt` would.
+
+mod rhai_to_tree;
+mod yuck_to_tree;
+
+use crate::error::DiagInfo;
+use crate::formatter::{FmtOptions, format_xml};
+use crate::transpiler::{InternalTree, convert_tree_to_xml};
+use crate::{FileInfo, TranspileOptions};
+
+pub use rhai_to_tree::rhai_to_internal_tree;
+pub use yuck_to_tree::yuck_to_internal_tree;
+
+/// [`Imported`] is the xml of an imported config.
+pub struct Imported {
+    pub xml: String,
+    /// Parts of the config that were left out of the xml.
+    pub warnings: Vec<String>,
+}
+
+/// Import the Rhai script in `file_info` as xml.
+/// Returns every construct that could not be imported as the error.
+pub fn import_rhai(
+    file_info: &FileInfo,
+    options: &TranspileOptions,
+    fmt_options: &FmtOptions,
+) -> Result<Imported, Vec<DiagInfo<'static>>> {
+    let tree = rhai_to_internal_tree(file_info.script, &options.widgets)?;
+    let xml = tree_to_xml(file_info, &tree, options, fmt_options);
+    Ok(Imported { xml, warnings: Vec::new() })
+}
+
+/// Import the eww yuck config in `file_info` as xml.
+/// Returns every construct that could not be imported as the error.
+pub fn import_yuck(
+    file_info: &FileInfo,
+    options: &TranspileOptions,
+    fmt_options: &FmtOptions,
+) -> Result<Imported, Vec<DiagInfo<'static>>> {
+    let (tree, warnings) = yuck_to_internal_tree(file_info.script, &options.widgets)?;
+    let xml = tree_to_xml(file_info, &tree, options, fmt_options);
+    Ok(Imported { xml, warnings })
+}
+
+fn tree_to_xml(
+    file_info: &FileInfo,
+    tree: &InternalTree,
+    options: &TranspileOptions,
+    fmt_options: &FmtOptions,
+) -> String {
+    let xml = convert_tree_to_xml(tree, &options.widgets);
+    let xml_info = FileInfo { file_path: file_info.file_path, script: &xml };
+
+    format_xml(&xml_info, fmt_options).expect("Imported xml should be valid")
+}
@@ -0,0 +1,407 @@
+use crate::error::DiagInfo;
+use crate::transpiler::{Attr, InternalTree, Span, position_span, widget_node};
+use crate::widgets::{WIDGETS, WidgetKind};
+use rhai::{AST, ASTNode, Engine, Expr, FnCallExpr, OptimizationLevel, Position, Stmt};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+/// Properties that are maps in Rhai, and `name.key` attributes in xml.
+const MAP_PROPERTIES: [&str; 2] = ["geometry", "reserve"];
+
+/// Convert a Rhai script into an [`InternalTree`].
+///
+/// The script should call `enter` with the windows, polls and listens.
+/// Functions without parameters that return a single widget are inlined
+/// where they are called. `widgets` are the custom widgets of the project,
+/// from the xml element name to the rhai function.
+pub fn rhai_to_internal_tree(
+    script: &str,
+    widgets: &BTreeMap<String, String>,
+) -> Result<InternalTree<'static>, Vec<DiagInfo<'static>>> {
+    let mut engine = Engine::new_raw();
+    // The AST should stay as close to the script as possible
+    engine.set_optimization_level(OptimizationLevel::None);
+    // Configs nest widgets deeper than the default limits allow
+    engine.set_max_expr_depths(0, 0);
+
+    let ast = engine.compile(script).map_err(|e| {
+        vec![DiagInfo {
+            code: "IE01",
+            message: format!("The Rhai script failed to parse: {}", e.err_type()),
+            label: Some("here"),
+            note: None,
+            span: position_span(script, e.position()),
+        }]
+    })?;
+
+    let mut importer = Importer {
+        script,
+        functions: function_bodies(&ast),
+        widgets,
+        inlining: Vec::new(),
+        errors: Vec::new(),
+    };
+
+    let mut root = None;
+
+    for stmt in ast.statements() {
+        match stmt_call(stmt) {
+            Some((call, pos)) if call.name == "enter" && root.is_none() => {
+                root = Some(importer.enter(call, pos));
+            }
+            _ => importer.unsupported(
+                stmt.position(),
+                "Only a single `enter` call can be imported at the top of the script.",
+            ),
+        }
+    }
+
+    let root = match root {
+        Some(root) => root,
+        None => {
+            importer.errors.push(DiagInfo {
+                code: "IE02",
+                message: "The Rhai script does not call `enter`.".to_string(),
+                label: None,
+                note: Some("`enter` is what becomes the <Root> element."),
+                span: None,
+            });
+            None
+        }
+    };
+
+    match root {
+        Some(root) if importer.errors.is_empty() => Ok(root),
+        _ => Err(importer.errors),
+    }
+}
+
+struct Importer<'s> {
+    script: &'s str,
+    /// Statements of the functions without parameters, by name.
+    functions: BTreeMap<String, Vec<Stmt>>,
+    widgets: &'s BTreeMap<String, String>,
+    /// Functions that are being inlined, to catch recursion.
+    inlining: Vec<String>,
+    errors: Vec<DiagInfo<'static>>,
+}
+
+impl Importer<'_> {
+    fn enter(&mut self, call: &FnCallExpr, pos: Position) -> Option<InternalTree<'static>> {
+        let [Expr::Array(items, _)] = call.args.as_slice() else {
+            self.unsupported(pos, "`enter` should be called with an array.");
+            return None;
+        };
+
+        let children = self.widgets(items);
+        Some(InternalTree::Enter { children, span: no_span() })
+    }
+
+    /// Convert every widget in `items`, skipping the ones that fail.
+    fn widgets(&mut self, items: &[Expr]) -> Vec<InternalTree<'static>> {
+        let mut children = Vec::new();
+
+        for item in items {
+            let child = match expr_call(item) {
+                Some((call, pos)) => self.widget(call, pos),
+                None => {
+                    self.unsupported(item.position(), "Only widgets can be imported here.");
+                    None
+                }
+            };
+
+            children.extend(child);
+        }
+
+        children
+    }
+
+    fn widget(&mut self, call: &FnCallExpr, pos: Position) -> Option<InternalTree<'static>> {
+        let name = call.name.as_str();
+
+        if call.args.is_empty() && self.functions.contains_key(name) {
+            return self.inline(name, pos);
+        }
+
+        match (name, call.args.as_slice()) {
+            ("defwindow", [window_name, props, node]) => {
+                let name = self.string(window_name)?;
+                let attrs = self.attrs(props)?;
+                let Some((call, pos)) = expr_call(node) else {
+                    self.unsupported(node.position(), "Only widgets can be imported here.");
+                    return None;
+                };
+                let node = Box::new(self.widget(call, pos)?);
+
+                Some(InternalTree::DefWindow { name: name.into(), attrs, node, span: no_span() })
+            }
+            ("poll" | "listen", [var, props]) => {
+                let var = self.string(var)?;
+                let mut attrs = self.attrs(props)?;
+
+                // The variable is the name attribute in xml
+                if !attrs.iter().any(|a| a.key == "name") {
+                    attrs.insert(0, Attr { key: "name".into(), value: var.clone().into() });
+                }
+
+                let (var, span) = (var.into(), no_span());
+                match name {
+                    "poll" => Some(InternalTree::Poll { var, attrs, span }),
+                    _ => Some(InternalTree::Listen { var, attrs, span }),
+                }
+            }
+            ("defwindow" | "poll" | "listen", _) => {
+                self.unsupported(pos, "This call has the wrong number of arguments.");
+                None
+            }
+            (_, args) => {
+                let (attrs, children) = match args {
+                    [] => (vec![], vec![]),
+                    [props] => (self.attrs(props)?, vec![]),
+                    [props, Expr::Array(items, _)] => (self.attrs(props)?, self.widgets(items)),
+                    _ => {
+                        self.unsupported(pos, "Widgets take a map and an array of children.");
+                        return None;
+                    }
+                };
+
+                let builtin = WIDGETS.iter().find(|w| {
+                    w.function == name && matches!(w.kind, WidgetKind::Container | WidgetKind::Leaf)
+                });
+
+                if let Some(builtin) = builtin {
+                    return widget_node(builtin.name, attrs, children, no_span());
+                }
+
+                if self.widgets.values().any(|f| f == name) {
+                    let function = Cow::Owned(name.to_string());
+                    return Some(InternalTree::Custom {
+                        function,
+                        attrs,
+                        children,
+                        span: no_span(),
+                    });
+                }
+
+                self.errors.push(DiagInfo {
+                    code: "IE03",
+                    message: format!("Unknown widget function: '{}'", name),
+                    label: Some("called here"),
+                    note: Some(
+                        "Custom widgets can be added in the [widgets] table of xmlman.toml.",
+                    ),
+                    span: position_span(self.script, pos),
+                });
+                None
+            }
+        }
+    }
+
+    /// Import the widget that the function `name` returns.
+    fn inline(&mut self, name: &str, pos: Position) -> Option<InternalTree<'static>> {
+        if self.inlining.iter().any(|f| f == name) {
+            self.unsupported(pos, "Recursive functions can not be imported.");
+            return None;
+        }
+
+        let body = self.functions[name].clone();
+        let [stmt] = body.as_slice() else {
+            self.unsupported(pos, "Only functions that return a single widget can be imported.");
+            return None;
+        };
+
+        let Some((call, call_pos)) = stmt_call(stmt) else {
+            self.unsupported(stmt.position(), "Only widgets can be imported here.");
+            return None;
+        };
+
+        self.inlining.push(name.to_string());
+        let widget = self.widget(call, call_pos);
+        self.inlining.pop();
+
+        widget
+    }
+
+    /// Convert a map of properties into attributes.
+    fn attrs(&mut self, props: &Expr) -> Option<Vec<Attr<'static>>> {
+        let Expr::Map(map, _) = props else {
+            self.unsupported(props.position(), "Properties should be a map.");
+            return None;
+        };
+
+        let mut attrs = Vec::new();
+        let mut failed = false;
+
+        for (key, value) in &map.0 {
+            match value {
+                Expr::Map(inner, _) if MAP_PROPERTIES.contains(&key.name.as_str()) => {
+                    for (inner_key, value) in &inner.0 {
+                        let key = format!("{}.{}", key.name, inner_key.name);
+                        match self.value(value) {
+                            Some(value) => {
+                                attrs.push(Attr { key: key.into(), value: value.into() })
+                            }
+                            None => failed = true,
+                        }
+                    }
+                }
+                _ => match self.value(value) {
+                    Some(value) => {
+                        attrs.push(Attr { key: key.name.to_string().into(), value: value.into() })
+                    }
+                    None => failed = true,
+                },
+            }
+        }
+
+        (!failed).then_some(attrs)
+    }
+
+    /// Convert a property value into an attribute value.
+    /// Values that are not strings are kept as code with `@no_quote`.
+    fn value(&mut self, value: &Expr) -> Option<String> {
+        let code = match value {
+            Expr::StringConstant(s, _) => return Some(s.to_string()),
+            // Attributes are transpiled to template strings,
+            // so the interpolation keeps working.
+            Expr::InterpolatedString(parts, _) => {
+                let mut interpolated = String::new();
+
+                for part in parts {
+                    let var = match part {
+                        Expr::StringConstant(s, _) => {
+                            interpolated.push_str(s);
+                            continue;
+                        }
+                        // Interpolations are blocks of statements
+                        Expr::Stmt(block) => block_variable(block.statements()),
+                        _ => None,
+                    };
+
+                    let Some(var) = var else {
+                        self.unsupported(
+                            part.position(),
+                            "Only variables can be imported in a template string.",
+                        );
+                        return None;
+                    };
+
+                    interpolated.push_str(&format!("${{{}}}", var));
+                }
+
+                return Some(interpolated);
+            }
+            Expr::IntegerConstant(i, _) => i.to_string(),
+            Expr::FloatConstant(f, _) => f.to_string(),
+            Expr::BoolConstant(b, _) => b.to_string(),
+            Expr::Unit(_) => "()".to_string(),
+            Expr::Variable(var, _, _) if var.2.is_empty() => var.1.to_string(),
+            _ => {
+                self.unsupported(value.position(), "This value can not be imported.");
+                return None;
+            }
+        };
+
+        Some(format!("@no_quote({})", code))
+    }
+
+    fn string(&mut self, value: &Expr) -> Option<String> {
+        match value {
+            Expr::StringConstant(s, _) => Some(s.to_string()),
+            _ => {
+                self.unsupported(value.position(), "Expected a string.");
+                None
+            }
+        }
+    }
+
+    fn unsupported(&mut self, pos: Position, message: &str) {
+        self.errors.push(DiagInfo {
+            code: "IE02",
+            message: message.to_string(),
+            label: Some("here"),
+            note: Some("Move this part of the config into a rhai file of its own."),
+            span: position_span(self.script, pos),
+        });
+    }
+}
+
+/// The function call that a statement consists of.
+fn stmt_call(stmt: &Stmt) -> Option<(&FnCallExpr, Position)> {
+    match stmt {
+        Stmt::FnCall(call, pos) => Some((call, *pos)),
+        Stmt::Expr(expr) => expr_call(expr),
+        Stmt::Return(Some(expr), _, _) => expr_call(expr),
+        _ => None,
+    }
+}
+
+/// The function call that an expression consists of.
+fn expr_call(expr: &Expr) -> Option<(&FnCallExpr, Position)> {
+    match expr {
+        Expr::FnCall(call, pos) => Some((call, *pos)),
+        Expr::Stmt(block) => match block.statements() {
+            [stmt] => stmt_call(stmt),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The variable that a block consists of.
+fn block_variable(statements: &[Stmt]) -> Option<&str> {
+    match statements {
+        [Stmt::Expr(expr)] => match expr.as_ref() {
+            Expr::Variable(var, _, _) if var.2.is_empty() => Some(var.1.as_str()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Statements of the functions without parameters, by name.
+///
+/// Rhai doesn't export the type of a function body, so each function is
+/// cloned into an AST of its own, where the statements at the top of the
+/// walk are exactly the statements of its body.
+fn function_bodies(ast: &AST) -> BTreeMap<String, Vec<Stmt>> {
+    ast.iter_fn_def()
+        .filter(|f| f.params.is_empty())
+        .map(|f| {
+            let mut function = ast.clone_functions_only();
+            function.retain_functions(|_, _, name, params| name == f.name.as_str() && params == 0);
+
+            let mut body = Vec::new();
+            function.walk(&mut |path: &[ASTNode]| {
+                if let [ASTNode::Stmt(stmt)] = path {
+                    body.push((*stmt).clone());
+                }
+                true
+            });
+
+            (f.name.to_string(), body)
+        })
+        .collect()
+}
+
+/// Imported elements don't point into any xml.
+fn no_span() -> Span {
+    Span { start: None, end: None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn function_bodies_on_one_line() {
+        // Minified scripts put every function and the entry on a single line
+        let script = "fn a(){let x=1;label(#{})}fn b(){box(#{},[a()])}fn c(x){x}enter([b()]);";
+        let ast = Engine::new_raw().compile(script).unwrap();
+        let bodies = function_bodies(&ast);
+
+        assert_eq!(bodies.keys().collect::<Vec<_>>(), ["a", "b"]);
+        assert_eq!(bodies["a"].len(), 2);
+        assert_eq!(bodies["b"].len(), 1);
+    }
+}
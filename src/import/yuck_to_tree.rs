@@ -0,0 +1,696 @@
+use crate::error::DiagInfo;
+use crate::transpiler::{Attr, InternalTree, Span, widget_node};
+use crate::widgets::{WIDGETS, WidgetKind};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+/// Window properties that are written as a form in yuck, like
+/// `:geometry (geometry :x "0%")`, and as `name.key` attributes in xml.
+const MAP_PROPERTIES: [&str; 2] = ["geometry", "reserve"];
+
+/// Convert a yuck config into an [`InternalTree`].
+///
+/// Every `defwindow`, `defpoll` and `deflisten` becomes a child of the
+/// root. Widgets from `defwidget` are inlined where they are used, with
+/// their parameters replaced by the values that they are passed.
+/// `widgets` are the custom widgets of the project, from the xml element
+/// name to the rhai function.
+///
+/// xml has no variables besides polls and listens, so every `defvar` is
+/// skipped. The tree is returned with a warning for each of them.
+pub fn yuck_to_internal_tree(
+    config: &str,
+    widgets: &BTreeMap<String, String>,
+) -> Result<(InternalTree<'static>, Vec<String>), Vec<DiagInfo<'static>>> {
+    let forms = Reader { config, pos: 0 }.read_all().map_err(|(message, span)| {
+        vec![DiagInfo {
+            code: "IE01",
+            message: format!("The yuck config failed to parse: {}", message),
+            label: Some("here"),
+            note: None,
+            span: Some(span),
+        }]
+    })?;
+
+    let mut importer =
+        Importer { defwidgets: BTreeMap::new(), widgets, inlining: Vec::new(), errors: Vec::new() };
+
+    // Widgets can be used before they are defined
+    for form in &forms {
+        if let Some(("defwidget", items)) = form.as_form() {
+            importer.defwidget(form, items);
+        }
+    }
+
+    let mut children = Vec::new();
+    let mut warnings = Vec::new();
+
+    for form in &forms {
+        let child = match form.as_form() {
+            Some(("defwidget", _)) => continue,
+            Some(("defvar", items)) => {
+                let name = match items.first() {
+                    Some(SExpr::Symbol(name, _)) => name,
+                    _ => "",
+                };
+                let line = config[..form.span().start].matches('\n').count() + 1;
+
+                warnings.push(format!(
+                    "Skipped `defvar {}` on line {}, as xml only has polls and listens.",
+                    name, line
+                ));
+                continue;
+            }
+            Some(("defwindow", items)) => importer.defwindow(form, items),
+            Some((head @ ("defpoll" | "deflisten"), items)) => importer.defvar(head, form, items),
+            _ => {
+                importer.unsupported(
+                    form.span(),
+                    "Only defwindow, defpoll, deflisten and defwidget can be imported.",
+                );
+                None
+            }
+        };
+
+        children.extend(child);
+    }
+
+    match importer.errors.is_empty() {
+        true => Ok((InternalTree::Enter { children, span: no_span() }, warnings)),
+        false => Err(importer.errors),
+    }
+}
+
+/// A yuck s-expression.
+#[derive(Debug)]
+enum SExpr<'s> {
+    List(Vec<SExpr<'s>>, Range<usize>),
+    Array(Vec<SExpr<'s>>, Range<usize>),
+    Keyword(&'s str, Range<usize>),
+    /// Names, numbers and booleans.
+    Symbol(&'s str, Range<usize>),
+    String(String, Range<usize>),
+    /// A `{ ... }` expression, without the braces.
+    Expr(&'s str, Range<usize>),
+}
+
+impl<'s> SExpr<'s> {
+    fn span(&self) -> Range<usize> {
+        match self {
+            SExpr::List(_, span)
+            | SExpr::Array(_, span)
+            | SExpr::Keyword(_, span)
+            | SExpr::Symbol(_, span)
+            | SExpr::String(_, span)
+            | SExpr::Expr(_, span) => span.clone(),
+        }
+    }
+
+    /// The name and the items after it, if this is a `(name ...)` form.
+    fn as_form(&self) -> Option<(&'s str, &[SExpr<'s>])> {
+        match self {
+            SExpr::List(items, _) => match items.split_first() {
+                Some((SExpr::Symbol(name, _), rest)) => Some((name, rest)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Reads the s-expressions of a yuck config.
+struct Reader<'s> {
+    config: &'s str,
+    pos: usize,
+}
+
+type ReadError = (&'static str, Range<usize>);
+
+impl<'s> Reader<'s> {
+    fn read_all(mut self) -> Result<Vec<SExpr<'s>>, ReadError> {
+        let mut forms = Vec::new();
+
+        while self.skip_whitespace() {
+            forms.push(self.read()?);
+        }
+
+        Ok(forms)
+    }
+
+    /// Skip whitespace and comments.
+    /// Returns `false` at the end of the config.
+    fn skip_whitespace(&mut self) -> bool {
+        loop {
+            let rest = &self.config[self.pos..];
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+
+            if !trimmed.starts_with(';') {
+                return !trimmed.is_empty();
+            }
+
+            self.pos += trimmed.find('\n').unwrap_or(trimmed.len());
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.config[self.pos..].chars().next()
+    }
+
+    fn read(&mut self) -> Result<SExpr<'s>, ReadError> {
+        let start = self.pos;
+
+        match self.peek() {
+            Some('(') => {
+                let items = self.read_items(')')?;
+                Ok(SExpr::List(items, start..self.pos))
+            }
+            Some('[') => {
+                let items = self.read_items(']')?;
+                Ok(SExpr::Array(items, start..self.pos))
+            }
+            Some(quote @ ('"' | '\'' | '`')) => self.read_string(quote),
+            Some('{') => self.read_expr(),
+            Some(')' | ']' | '}') => Err(("unexpected closing bracket", start..start + 1)),
+            _ => {
+                let len = self.config[start..]
+                    .find(|c: char| c.is_whitespace() || "()[]{}\"'`;".contains(c))
+                    .unwrap_or(self.config.len() - start);
+                self.pos += len;
+
+                let atom = &self.config[start..self.pos];
+                match atom.strip_prefix(':') {
+                    Some(keyword) => Ok(SExpr::Keyword(keyword, start..self.pos)),
+                    None => Ok(SExpr::Symbol(atom, start..self.pos)),
+                }
+            }
+        }
+    }
+
+    fn read_items(&mut self, close: char) -> Result<Vec<SExpr<'s>>, ReadError> {
+        let start = self.pos;
+        self.pos += 1;
+        let mut items = Vec::new();
+
+        loop {
+            if !self.skip_whitespace() {
+                return Err(("unclosed bracket", start..start + 1));
+            }
+
+            if self.peek() == Some(close) {
+                self.pos += 1;
+                return Ok(items);
+            }
+
+            items.push(self.read()?);
+        }
+    }
+
+    fn read_string(&mut self, quote: char) -> Result<SExpr<'s>, ReadError> {
+        let start = self.pos;
+        let mut string = String::new();
+        let mut chars = self.config[start + 1..].char_indices();
+
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some((_, 'n')) => string.push('\n'),
+                    Some((_, 't')) => string.push('\t'),
+                    Some((_, escaped)) => string.push(escaped),
+                    None => break,
+                },
+                c if c == quote => {
+                    self.pos = start + 1 + i + 1;
+                    return Ok(SExpr::String(string, start..self.pos));
+                }
+                c => string.push(c),
+            }
+        }
+
+        Err(("unclosed string", start..start + 1))
+    }
+
+    fn read_expr(&mut self) -> Result<SExpr<'s>, ReadError> {
+        let start = self.pos;
+        let mut depth = 0;
+        let mut quote = None;
+
+        for (i, c) in self.config[start..].char_indices() {
+            match (quote, c) {
+                (Some(q), c) if c == q => quote = None,
+                (Some(_), _) => {}
+                (None, '"' | '\'' | '`') => quote = Some(c),
+                (None, '{') => depth += 1,
+                (None, '}') => {
+                    depth -= 1;
+                    if depth == 0 {
+                        self.pos = start + i + 1;
+                        let expr = self.config[start + 1..start + i].trim();
+                        return Ok(SExpr::Expr(expr, start..self.pos));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Err(("unclosed expression", start..start + 1))
+    }
+}
+
+/// The value of a property, after the parameters of widgets are replaced.
+#[derive(Debug, Clone)]
+enum Value {
+    /// A string, which is an attribute as it is.
+    Text(String),
+    /// Anything else is code, that is kept with `@no_quote`.
+    Code(String),
+    /// An optional parameter that was not passed. Properties that
+    /// are set to it are left out, and it is empty everywhere else.
+    Unset,
+}
+
+/// The parameters and children of the widget that is being inlined.
+#[derive(Default)]
+struct Scope {
+    params: BTreeMap<String, Value>,
+    children: Vec<InternalTree<'static>>,
+}
+
+struct Importer<'s, 'f> {
+    /// Parameters and body of every `defwidget`, by name.
+    defwidgets: BTreeMap<&'s str, (Vec<&'s str>, &'f SExpr<'s>)>,
+    widgets: &'f BTreeMap<String, String>,
+    /// Widgets that are being inlined, to catch recursion.
+    inlining: Vec<&'s str>,
+    errors: Vec<DiagInfo<'static>>,
+}
+
+impl<'s, 'f> Importer<'s, 'f> {
+    fn defwidget(&mut self, form: &'f SExpr<'s>, items: &'f [SExpr<'s>]) {
+        let [SExpr::Symbol(name, _), SExpr::Array(params, _), body] = items else {
+            self.unsupported(
+                form.span(),
+                "A defwidget should have a name, parameters and a widget.",
+            );
+            return;
+        };
+
+        let mut names = Vec::new();
+        for param in params {
+            match param {
+                SExpr::Symbol(param, _) => names.push(param.trim_start_matches('?')),
+                _ => self.unsupported(param.span(), "Expected a parameter name."),
+            }
+        }
+
+        self.defwidgets.insert(name, (names, body));
+    }
+
+    fn defwindow(
+        &mut self,
+        form: &'f SExpr<'s>,
+        items: &'f [SExpr<'s>],
+    ) -> Option<InternalTree<'static>> {
+        let Some((SExpr::Symbol(name, _), rest)) = items.split_first() else {
+            self.unsupported(form.span(), "A defwindow should have a name.");
+            return None;
+        };
+
+        let scope = Scope::default();
+        let (attrs, positional) = self.props(rest, &scope)?;
+
+        let [node] = positional.as_slice() else {
+            self.unsupported(form.span(), "A defwindow should contain exactly one widget.");
+            return None;
+        };
+
+        let node = Box::new(self.widget(node, &scope)?);
+        Some(InternalTree::DefWindow {
+            name: name.to_string().into(),
+            attrs,
+            node,
+            span: no_span(),
+        })
+    }
+
+    /// Import a `defpoll` or `deflisten`. The command is the last item.
+    fn defvar(
+        &mut self,
+        head: &str,
+        form: &'f SExpr<'s>,
+        items: &'f [SExpr<'s>],
+    ) -> Option<InternalTree<'static>> {
+        let Some((SExpr::Symbol(var, _), rest)) = items.split_first() else {
+            self.unsupported(form.span(), "Expected the name of the variable.");
+            return None;
+        };
+
+        let (mut attrs, positional) = self.props(rest, &Scope::default())?;
+
+        let [SExpr::String(cmd, _)] = positional.as_slice() else {
+            self.unsupported(form.span(), "Expected a single command string.");
+            return None;
+        };
+
+        attrs.insert(0, Attr { key: "name".into(), value: var.to_string().into() });
+        attrs.push(Attr { key: "cmd".into(), value: cmd.clone().into() });
+
+        let (var, span) = (Cow::Owned(var.to_string()), no_span());
+        match head {
+            "defpoll" => Some(InternalTree::Poll { var, attrs, span }),
+            _ => Some(InternalTree::Listen { var, attrs, span }),
+        }
+    }
+
+    fn widget(&mut self, expr: &'f SExpr<'s>, scope: &Scope) -> Option<InternalTree<'static>> {
+        // Text is shown as a label
+        if is_text(expr) {
+            let attrs = vec![self.attr("text", expr, scope)?];
+            return Some(InternalTree::Label { attrs, span: no_span() });
+        }
+
+        let Some((name, items)) = expr.as_form() else {
+            self.unsupported(expr.span(), "Expected a widget.");
+            return None;
+        };
+
+        if let Some(&(ref params, body)) = self.defwidgets.get(name) {
+            let params = params.clone();
+            return self.inline(name, &params, body, expr, items, scope);
+        }
+
+        let (mut attrs, mut positional) = self.props(items, scope)?;
+
+        // Widget names are kebab-case in yuck
+        let function = match name.replace('-', "").as_str() {
+            "scale" => "slider".to_string(),
+            function => function.to_string(),
+        };
+
+        let builtin = WIDGETS.iter().find(|w| {
+            w.function == function && matches!(w.kind, WidgetKind::Container | WidgetKind::Leaf)
+        });
+
+        if let Some(builtin) = builtin.filter(|w| w.kind == WidgetKind::Leaf) {
+            // Like `(button "Click")`, which has a label property in ewwii
+            if let [text] = positional.as_slice()
+                && is_text(text)
+                && builtin.attributes.contains(&"label")
+            {
+                attrs.push(self.attr("label", text, scope)?);
+                positional.clear();
+            }
+
+            if !positional.is_empty() {
+                self.unsupported(expr.span(), "This widget can not have children.");
+                return None;
+            }
+        }
+
+        let children = self.children(&positional, scope);
+
+        if let Some(builtin) = builtin {
+            return widget_node(builtin.name, attrs, children, no_span());
+        }
+
+        if let Some(function) = self.widgets.values().find(|f| *f == name || **f == function) {
+            let function = Cow::Owned(function.clone());
+            return Some(InternalTree::Custom { function, attrs, children, span: no_span() });
+        }
+
+        self.errors.push(DiagInfo {
+            code: "IE03",
+            message: format!("Unknown widget: '{}'", name),
+            label: Some("used here"),
+            note: Some("Custom widgets can be added in the [widgets] table of xmlman.toml."),
+            span: Some(expr.span()),
+        });
+        None
+    }
+
+    /// Import the body of a `defwidget`, with the parameters
+    /// and children that it is used with.
+    fn inline(
+        &mut self,
+        name: &'s str,
+        params: &[&'s str],
+        body: &'f SExpr<'s>,
+        expr: &'f SExpr<'s>,
+        items: &'f [SExpr<'s>],
+        scope: &Scope,
+    ) -> Option<InternalTree<'static>> {
+        if self.inlining.contains(&name) {
+            self.unsupported(expr.span(), "Recursive widgets can not be imported.");
+            return None;
+        }
+
+        let mut inner = Scope {
+            params: params.iter().map(|p| (p.to_string(), Value::Unset)).collect(),
+            children: Vec::new(),
+        };
+
+        let mut positional = Vec::new();
+        let mut items = items.iter();
+
+        while let Some(item) = items.next() {
+            match item {
+                SExpr::Keyword(key, span) => {
+                    let Some(value) = items.next() else {
+                        self.unsupported(span.clone(), "This property has no value.");
+                        return None;
+                    };
+                    let value = self.value(value, scope)?;
+                    inner.params.insert(key.to_string(), value);
+                }
+                item => positional.push(item),
+            }
+        }
+
+        inner.children = self.children(&positional, scope);
+
+        self.inlining.push(name);
+        let widget = self.widget(body, &inner);
+        self.inlining.pop();
+
+        widget
+    }
+
+    fn children(
+        &mut self,
+        positional: &[&'f SExpr<'s>],
+        scope: &Scope,
+    ) -> Vec<InternalTree<'static>> {
+        let mut children = Vec::new();
+
+        for item in positional {
+            // The children that the current widget was used with
+            match item.as_form() {
+                Some(("children", [])) => {
+                    children.extend(scope.children.iter().cloned());
+                    continue;
+                }
+                Some(("children", _)) => {
+                    self.unsupported(item.span(), "Only all of the children can be imported.");
+                    continue;
+                }
+                _ => {}
+            }
+
+            children.extend(self.widget(item, scope));
+        }
+
+        children
+    }
+
+    /// Split the items of a form into attributes, from its `:key value`
+    /// properties, and the other items.
+    fn props(
+        &mut self,
+        items: &'f [SExpr<'s>],
+        scope: &Scope,
+    ) -> Option<(Vec<Attr<'static>>, Vec<&'f SExpr<'s>>)> {
+        let mut attrs = Vec::new();
+        let mut positional = Vec::new();
+        let mut failed = false;
+        let mut items = items.iter();
+
+        while let Some(item) = items.next() {
+            let SExpr::Keyword(key, span) = item else {
+                positional.push(item);
+                continue;
+            };
+
+            let Some(value) = items.next() else {
+                self.unsupported(span.clone(), "This property has no value.");
+                failed = true;
+                continue;
+            };
+
+            // Properties are kebab-case in yuck, and snake_case in ewwii
+            let key = key.replace('-', "_");
+
+            if let (true, Some((_, inner))) =
+                (MAP_PROPERTIES.contains(&key.as_str()), value.as_form())
+            {
+                let Some((inner_attrs, rest)) = self.props(inner, scope) else {
+                    failed = true;
+                    continue;
+                };
+
+                if let Some(extra) = rest.first() {
+                    self.unsupported(extra.span(), "Expected a property.");
+                    failed = true;
+                }
+
+                for attr in inner_attrs {
+                    let key = format!("{}.{}", key, attr.key);
+                    attrs.push(Attr { key: key.into(), value: attr.value });
+                }
+                continue;
+            }
+
+            match self.value(value, scope) {
+                Some(value) => attrs.extend(to_attr(key, value)),
+                None => failed = true,
+            }
+        }
+
+        (!failed).then_some((attrs, positional))
+    }
+
+    /// Convert a value into the attribute `key`, which is empty
+    /// for an optional parameter that was not passed.
+    fn attr(&mut self, key: &str, expr: &SExpr, scope: &Scope) -> Option<Attr<'static>> {
+        let value = match self.value(expr, scope)? {
+            Value::Unset => Value::Text(String::new()),
+            value => value,
+        };
+        to_attr(key.to_string(), value)
+    }
+
+    fn value(&mut self, expr: &SExpr, scope: &Scope) -> Option<Value> {
+        match expr {
+            SExpr::String(text, _) => Some(Value::Text(substitute(text, scope))),
+            SExpr::Symbol(symbol, _) => match scope.params.get(*symbol) {
+                Some(value) => Some(value.clone()),
+                // Numbers, booleans and variables
+                None => Some(Value::Code(symbol.to_string())),
+            },
+            SExpr::Expr(expr, _) => Some(Value::Code(substitute_expr(expr, scope))),
+            _ => {
+                self.unsupported(expr.span(), "This value can not be imported.");
+                None
+            }
+        }
+    }
+
+    fn unsupported(&mut self, span: Range<usize>, message: &str) {
+        self.errors.push(DiagInfo {
+            code: "IE02",
+            message: message.to_string(),
+            label: Some("here"),
+            note: Some("Move this part of the config into a rhai file of its own."),
+            span: Some(span),
+        });
+    }
+}
+
+/// The attribute for a value, or `None` for an optional parameter that was not passed.
+/// Values that are not strings are kept as code with `@no_quote`.
+fn to_attr(key: String, value: Value) -> Option<Attr<'static>> {
+    let value = match value {
+        Value::Text(text) => text,
+        Value::Code(code) => format!("@no_quote({})", code),
+        Value::Unset => return None,
+    };
+
+    Some(Attr { key: key.into(), value: value.into() })
+}
+
+/// Whether an item is shown as text, like `"Hello"`, `time` or `{a + b}`.
+fn is_text(expr: &SExpr) -> bool {
+    matches!(expr, SExpr::String(..) | SExpr::Symbol(..) | SExpr::Expr(..))
+}
+
+/// Replace the `${param}` interpolations in `text` with the values that
+/// the parameters of the current widget were passed.
+fn substitute(text: &str, scope: &Scope) -> String {
+    let mut text = text.to_string();
+
+    for (param, value) in &scope.params {
+        let replacement = match value {
+            Value::Text(value) => value.clone(),
+            Value::Code(code) => format!("${{{}}}", code),
+            Value::Unset => String::new(),
+        };
+
+        text = text.replace(&format!("${{{}}}", param), &replacement);
+    }
+
+    text
+}
+
+/// Replace the parameters of the current widget in an expression.
+/// Strings in the expression are left as they are.
+fn substitute_expr(expr: &str, scope: &Scope) -> String {
+    let mut out = String::new();
+    let mut quote = None;
+    let mut ident = String::new();
+
+    for c in expr.chars().chain(std::iter::once(' ')) {
+        if quote.is_none() && (c.is_alphanumeric() || c == '_') {
+            ident.push(c);
+            continue;
+        }
+
+        // Fields like `a.value` are not parameters
+        let is_field = out.ends_with('.');
+        match scope.params.get(ident.as_str()) {
+            Some(Value::Text(text)) if !is_field => out.push_str(&format!("{:?}", text)),
+            // Optional parameters are empty when they are not passed
+            Some(Value::Unset) if !is_field => out.push_str("\"\""),
+            Some(Value::Code(code))
+                if !is_field && code.chars().all(|c| c.is_alphanumeric() || c == '_') =>
+            {
+                out.push_str(code)
+            }
+            Some(Value::Code(code)) if !is_field => out.push_str(&format!("({})", code)),
+            _ => out.push_str(&ident),
+        }
+        ident.clear();
+
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (None, '"' | '\'' | '`') => quote = Some(c),
+            _ => {}
+        }
+        out.push(c);
+    }
+
+    out.pop();
+    out
+}
+
+/// Imported elements don't point into any xml.
+fn no_span() -> Span {
+    Span { start: None, end: None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defvar_is_skipped_with_a_warning() {
+        let config = "(defvar greeting \"Hi\")\n\n(defvar shown true)\n(defwindow bar (label :text greeting))";
+        let (tree, warnings) = yuck_to_internal_tree(config, &BTreeMap::new()).unwrap();
+
+        let InternalTree::Enter { children, .. } = tree else { panic!("expected the root") };
+        assert!(matches!(children.as_slice(), [InternalTree::DefWindow { .. }]));
+
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("`defvar greeting` on line 1"), "{}", warnings[0]);
+        assert!(warnings[1].contains("`defvar shown` on line 3"), "{}", warnings[1]);
+    }
+}
@@ -0,0 +1,90 @@
+//! Project scaffolding for `xmlman init`.
+
+use crate::report::Report;
+use crate::{OutputOptions, transpile_file};
+
+use log::{error, info};
+use std::fs;
+use std::path::Path;
+use xmlman::InputFile;
+use xmlman::config::ProjectConfig;
+
+const CONFIG_TEMPLATE: &str = include_str!("../templates/ewwii.xml");
+const PROJECT_TEMPLATE: &str = include_str!("../templates/xmlman.toml");
+
+/// Create a starter project in `dir`.
+/// Returns `false` if the project could not be created.
+pub fn init(dir: &str) -> bool {
+    let root = Path::new(dir);
+    let project_file = root.join("xmlman.toml");
+
+    if project_file.exists() {
+        error!("'{}' already exists.", project_file.display());
+        return false;
+    }
+
+    let config_file = root.join("xml").join("ewwii.xml");
+
+    if config_file.exists() {
+        error!("'{}' already exists.", config_file.display());
+        return false;
+    }
+
+    if let Err(e) = fs::create_dir_all(root.join("xml")) {
+        error!("Failed to create '{}': {}", dir, e);
+        return false;
+    }
+
+    fs::write(&project_file, PROJECT_TEMPLATE).expect("Failed to write project file");
+    fs::write(&config_file, CONFIG_TEMPLATE).expect("Failed to write config file");
+
+    // Transpile the starter config right away, so that the
+    // scaffold is known to work with the current widget set.
+    let config = ProjectConfig::parse(&project_file, PROJECT_TEMPLATE)
+        .expect("The project template is not a valid project config");
+    let options = config.transpile_options();
+    let mut report = Report::default();
+    let transpiled = transpile_file(
+        &InputFile::from_file(config_file),
+        &options,
+        &OutputOptions::default(),
+        None,
+        None,
+        &mut report,
+    );
+    report.print();
+
+    if !transpiled {
+        error!("The starter config failed to transpile. This is a bug in xmlman.");
+        return false;
+    }
+
+    info!("[-] Created a new xmlman project in '{}'", dir);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xmlman::FileInfo;
+    use xmlman::formatter::format_xml;
+
+    #[test]
+    fn scaffold_is_formatted() {
+        let dir = std::env::temp_dir().join(format!("xmlman-init-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        assert!(init(&dir.to_string_lossy()));
+
+        let project_file = dir.join("xmlman.toml");
+        let config =
+            ProjectConfig::parse(&project_file, &fs::read_to_string(&project_file).unwrap())
+                .unwrap();
+        let script = fs::read_to_string(dir.join("xml").join("ewwii.xml")).unwrap();
+        let file_info = FileInfo { file_path: "ewwii.xml", script: &script };
+
+        assert_eq!(format_xml(&file_info, &config.fmt_options()).unwrap(), CONFIG_TEMPLATE);
+        assert!(dir.join("ewwii.rhai").is_file());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,272 @@
+//! Resolving the inputs given to xmlman into xml files.
+//!
+//! An input can be a file, a directory, a glob pattern or `-` for stdin.
+//! Directories are searched recursively for `*.xml` files. Every file remembers its
+//! path relative to the input it was found through, so that the output
+//! tree can mirror the input tree.
+
+use crate::TranspileOptions;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+
+/// The input that stands for stdin.
+pub const STDIN: &str = "-";
+
+/// [`InputFile`] is an xml file to transpile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputFile {
+    pub path: PathBuf,
+    /// Path of the file relative to the directory or glob it was found
+    /// through. For a file that was given directly, this is its file name.
+    pub relative: PathBuf,
+    /// Whether the content is read from stdin.
+    /// If so, `path` is only the name shown in diagnostics.
+    pub from_stdin: bool,
+}
+
+impl InputFile {
+    /// An input that was given directly as a file.
+    pub fn from_file(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let relative = PathBuf::from(path.file_name().unwrap_or_default());
+        InputFile { path, relative, from_stdin: false }
+    }
+
+    /// An input that is read from stdin.
+    /// `name` is shown in diagnostics in place of a file path.
+    pub fn stdin(name: Option<&str>) -> Self {
+        match name {
+            Some(name) => InputFile { from_stdin: true, ..InputFile::from_file(name) },
+            None => InputFile {
+                path: PathBuf::from("<stdin>"),
+                relative: PathBuf::from("stdin.xml"),
+                from_stdin: true,
+            },
+        }
+    }
+
+    /// Path of the transpiled file with `extension`, relative to the output directory.
+    pub fn output_relative(&self, extension: &str) -> PathBuf {
+        self.relative.with_extension(extension)
+    }
+}
+
+/// Whether `input` is a glob pattern rather than a path.
+pub fn is_glob(input: &str) -> bool {
+    input.contains(['*', '?', '['])
+}
+
+/// The directory that the relative paths of files found through `input` start from.
+/// Returns `None` if `input` is a single file.
+pub fn input_root(input: &str) -> Option<PathBuf> {
+    if is_glob(input) {
+        // Everything up to the first component with a wildcard in it
+        let root: PathBuf = Path::new(input)
+            .components()
+            .take_while(|c| !is_glob(&c.as_os_str().to_string_lossy()))
+            .collect();
+
+        Some(if root.as_os_str().is_empty() { PathBuf::from(".") } else { root })
+    } else if Path::new(input).is_dir() {
+        Some(PathBuf::from(input))
+    } else {
+        None
+    }
+}
+
+/// Resolve files, directories and glob patterns into the xml files to transpile.
+/// `stdin_name` is the name shown in diagnostics for the [`STDIN`] input.
+///
+/// Returns an error if an input does not match any file, or if two different
+/// files would be transpiled to the same output path with `options`.
+pub fn resolve_inputs(
+    inputs: &[String],
+    stdin_name: Option<&str>,
+    options: &TranspileOptions,
+) -> Result<Vec<InputFile>, String> {
+    let mut files = Vec::new();
+
+    for input in inputs {
+        let found = if input == STDIN {
+            vec![InputFile::stdin(stdin_name)]
+        } else if is_glob(input) {
+            let paths = glob::glob(input)
+                .map_err(|e| format!("Invalid input pattern '{}': {}", input, e))?;
+            let root = input_root(input).unwrap_or_default();
+
+            paths
+                .filter_map(|p| p.ok())
+                .filter(|p| p.is_file())
+                .map(|path| InputFile {
+                    relative: relative_to(&path, &root),
+                    path,
+                    from_stdin: false,
+                })
+                .collect()
+        } else if Path::new(input).is_dir() {
+            let mut paths = Vec::new();
+            collect_xml_files(Path::new(input), &mut paths);
+
+            paths
+                .into_iter()
+                .map(|path| InputFile {
+                    relative: relative_to(&path, Path::new(input)),
+                    path,
+                    from_stdin: false,
+                })
+                .collect()
+        } else if Path::new(input).is_file() {
+            vec![InputFile::from_file(input)]
+        } else {
+            return Err(format!("The file '{}' does not exist.", input));
+        };
+
+        if found.is_empty() {
+            return Err(format!("The input '{}' did not match any xml file.", input));
+        }
+
+        files.extend(found);
+    }
+
+    dedup_and_check_collisions(files, options)
+}
+
+/// Recursively collect all xml files in `dir`, sorted by path.
+pub fn collect_xml_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else { return };
+
+    let mut paths: Vec<PathBuf> = entries.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+    paths.sort();
+
+    for path in paths {
+        if path.is_dir() {
+            collect_xml_files(&path, files);
+        } else if is_xml(&path) {
+            files.push(path);
+        }
+    }
+}
+
+pub fn is_xml(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "xml")
+}
+
+/// `path` relative to `root`, falling back to the file name
+/// if `path` is not inside of `root`.
+pub fn relative_to(path: &Path, root: &Path) -> PathBuf {
+    let strip = |p: &Path| -> PathBuf {
+        p.components().filter(|c| !matches!(c, Component::CurDir)).collect()
+    };
+
+    match strip(path).strip_prefix(strip(root)) {
+        Ok(relative) => relative.to_path_buf(),
+        Err(_) => PathBuf::from(path.file_name().unwrap_or_default()),
+    }
+}
+
+/// [`OutputClaims`] are the files that each input is transpiled to.
+/// The modules of split windows are only known once a file is
+/// transpiled, so they are claimed right before they are written.
+/// It can be shared by the threads that transpile files in parallel.
+#[derive(Debug, Default)]
+pub struct OutputClaims {
+    /// Inputs by the absolute path of the file they are transpiled to.
+    claims: Mutex<BTreeMap<PathBuf, PathBuf>>,
+}
+
+impl OutputClaims {
+    /// Claims of the output paths of `files`, as `(output, input)` pairs.
+    pub fn new(files: impl IntoIterator<Item = (PathBuf, PathBuf)>) -> Self {
+        let claims = files.into_iter().map(|(output, input)| (absolute(&output), input)).collect();
+        OutputClaims { claims: Mutex::new(claims) }
+    }
+
+    /// Claim `output` for `input`. Fails if another input
+    /// is already transpiled to the same file.
+    pub fn claim(&self, output: &Path, input: &Path) -> Result<(), String> {
+        let mut claims = self.claims.lock().unwrap();
+
+        match claims.get(&absolute(output)) {
+            Some(other) if other != input => Err(format!(
+                "'{}' and '{}' would both be transpiled to '{}'.",
+                other.display(),
+                input.display(),
+                output.display()
+            )),
+            Some(_) => Ok(()),
+            None => {
+                claims.insert(absolute(output), input.to_path_buf());
+                Ok(())
+            }
+        }
+    }
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Drop files that were matched by more than one input, and make sure
+/// that no two files are transpiled to the same output path.
+fn dedup_and_check_collisions(
+    files: Vec<InputFile>,
+    options: &TranspileOptions,
+) -> Result<Vec<InputFile>, String> {
+    let mut unique: Vec<InputFile> = Vec::new();
+    let mut seen = Vec::new();
+    let mut outputs: BTreeMap<PathBuf, PathBuf> = BTreeMap::new();
+
+    for file in files {
+        let canonical = match file.from_stdin {
+            true => PathBuf::from(STDIN),
+            false => fs::canonicalize(&file.path).unwrap_or_else(|_| file.path.clone()),
+        };
+        if seen.contains(&canonical) {
+            continue;
+        }
+
+        let output = options.output_path(&file);
+        if let Some(other) = outputs.get(&output) {
+            return Err(format!(
+                "'{}' and '{}' would both be transpiled to '{}'.",
+                other.display(),
+                file.path.display(),
+                output.display()
+            ));
+        }
+
+        outputs.insert(output, file.path.clone());
+        seen.push(canonical);
+        unique.push(file);
+    }
+
+    Ok(unique)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outputs_are_claimed_by_one_input() {
+        let claims = OutputClaims::new([("out/a.rhai".into(), "a.xml".into())]);
+
+        assert!(claims.claim(Path::new("out/a.rhai"), Path::new("a.xml")).is_ok());
+        assert!(claims.claim(Path::new("out/a.rhai"), Path::new("b.xml")).is_err());
+
+        assert!(claims.claim(Path::new("out/windows/bar.rhai"), Path::new("a.xml")).is_ok());
+        assert!(claims.claim(Path::new("out/windows/bar.rhai"), Path::new("b.xml")).is_err());
+    }
+
+    #[test]
+    fn mirrored_outputs_collide() {
+        let options = TranspileOptions { out: Some("out".into()), ..Default::default() };
+        let files = vec![InputFile::from_file("a/bar.xml"), InputFile::from_file("b/bar.xml")];
+
+        let err = dedup_and_check_collisions(files, &options).unwrap_err();
+        let output = Path::new("out").join("bar.rhai");
+        assert!(err.contains(&format!("'{}'", output.display())), "{}", err);
+    }
+}
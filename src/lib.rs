@@ -0,0 +1,183 @@
+//! XmlMan: An elegant xml to rhai transpiler for ewwii.
+//!
+//! The transpiler can be driven one step at a time with [`parse`],
+//! [`to_internal_tree`], [`check`] and [`to_rhai`], or all at once
+//! with [`transpile`]. The generated script can then be compiled with
+//! [`verify`] to make sure that it is valid.
+//!
+//! Rhai is the default [`Target`]. [`generate`] uses the [`Backend`] of
+//! the target in the options, which can also emit yuck for eww.
+//!
+//! Full transpile process:
+//!
+//! ```text
+//! -----------      ------------------------
+//! | Read fs | ---> | Parse with xmlparser | ------------->
+//! -----------      ------------------------              |
+//!                              ---------------------------------------------------
+//!              --------------- | Convert XML AST to internal tree representation |
+//!              | [2nd]         ---------------------------------------------------
+//!              |                                                 | [1st]
+//!     ----------------------------------------                   |
+//!     | Internal tree then converted to Rhai |    ----------------------------------
+//!     ----------------------------------------    | Run checks to prevent mistakes |
+//!              |                                  ----------------------------------
+//!     ------------------------
+//!     | Optionally formatted |
+//!     ------------------------
+//!              |
+//!              |      ---------------
+//!              | ---> | Write to fs |
+//!                     ---------------
+//! ```
+
+pub mod cache;
+pub mod checks;
+pub mod config;
+pub mod dump;
+pub mod error;
+pub mod explain;
+pub mod formatter;
+pub mod import;
+pub mod inputs;
+pub mod parser;
+pub mod schema;
+pub mod transpiler;
+pub mod widgets;
+
+pub use checks::CheckError;
+pub use error::DiagInfo;
+pub use inputs::InputFile;
+pub use parser::{Ast, Node, NodeId};
+pub use transpiler::{Attr, Backend, Generated, Indent, InternalTree, RhaiStyle, Span, Target};
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// [`FileInfo`] is structure for holding both the
+/// file_path and xml content. It is used to send
+/// info to the transpiler so that it can print pretty
+/// errors whenever it need to.
+pub struct FileInfo<'a> {
+    pub file_path: &'a str,
+    pub script: &'a str,
+}
+
+/// Options that control how files are transpiled.
+#[derive(Debug, Clone, Default, Hash)]
+pub struct TranspileOptions {
+    /// Directory to write the transpiled files to.
+    /// Files are written next to the current directory if `None`.
+    pub out: Option<PathBuf>,
+    /// Custom widgets, from the xml element name
+    /// to the rhai function that creates the widget.
+    pub widgets: BTreeMap<String, String>,
+    /// Values that replace `@define(NAME)` in attributes.
+    pub defines: BTreeMap<String, String>,
+    /// Codes of the checks that should not run.
+    pub disabled_checks: Vec<String>,
+    /// The backend that generates the transpiled files.
+    pub target: Target,
+    /// How the generated Rhai code is laid out.
+    pub rhai_style: RhaiStyle,
+    /// Generate a module for each window, imported by the transpiled file.
+    pub split_windows: bool,
+}
+
+impl TranspileOptions {
+    /// Path that the transpiled version of `input` should be written to.
+    /// The output tree mirrors the directory structure of the inputs.
+    pub fn output_path(&self, input: &InputFile) -> PathBuf {
+        let out_file = input.output_relative(self.target.backend().extension());
+
+        match &self.out {
+            Some(out_dir) => out_dir.join(out_file),
+            None => out_file,
+        }
+    }
+}
+
+/// Errors that can stop [`transpile`].
+#[derive(Debug)]
+pub enum TranspileError<'a> {
+    /// An error that points to a location in the xml script.
+    Diag(DiagInfo<'a>),
+    /// One or more checks failed.
+    /// See [`checks::report_lines`] to report them.
+    Check(Vec<CheckError>),
+}
+
+impl<'a> From<DiagInfo<'a>> for TranspileError<'a> {
+    fn from(info: DiagInfo<'a>) -> Self {
+        TranspileError::Diag(info)
+    }
+}
+
+/// Parse the xml script into an [`Ast`] of [`Node`]s.
+pub fn parse<'a>(file_info: &FileInfo<'a>) -> Result<Ast<'a>, DiagInfo<'a>> {
+    parser::parse_xml(file_info)
+}
+
+/// Convert the parsed xml into an [`InternalTree`].
+/// The internal tree is a tree that stands between xml and rhai.
+/// It borrows from the [`Ast`], and like it, can be sent to other threads.
+pub fn to_internal_tree<'t>(
+    ast: &'t Ast<'_>,
+    options: &'t TranspileOptions,
+) -> Result<InternalTree<'t>, DiagInfo<'static>> {
+    transpiler::convert_node(ast, ast.root(), options)
+}
+
+/// Run all checks that are not disabled on the [`InternalTree`].
+pub fn check(tree: &InternalTree, options: &TranspileOptions) -> Result<(), Vec<CheckError>> {
+    checks::run_all_checks(tree, &options.disabled_checks)
+}
+
+/// Generate the Rhai script from the [`InternalTree`]. The tree is lowered
+/// into a [`transpiler::Script`], which is laid out by [`transpiler::print_rhai`].
+pub fn to_rhai(tree: &InternalTree, style: &RhaiStyle) -> Result<String, DiagInfo<'static>> {
+    transpiler::convert_tree(tree, style)
+}
+
+/// Generate the config of the target in `options` from the [`InternalTree`].
+pub fn generate(
+    tree: &InternalTree,
+    options: &TranspileOptions,
+) -> Result<String, DiagInfo<'static>> {
+    options.target.backend().generate(tree, options)
+}
+
+/// Compile the generated Rhai script to make sure that it is valid.
+pub fn verify(code: &str) -> Result<(), DiagInfo<'static>> {
+    transpiler::verify_rhai(code)
+}
+
+/// Run the full pipeline on the xml script and return the generated config,
+/// with the modules of its windows if they are split.
+///
+/// ```
+/// use xmlman::{FileInfo, TranspileOptions, transpile};
+///
+/// let xml_content = r#"<Root><Window name="bar"><Label text="Hello"/></Window></Root>"#;
+///
+/// let file_info = FileInfo { file_path: "bar.xml", script: xml_content };
+/// let rhai_code = transpile(&file_info, &TranspileOptions::default()).unwrap().code;
+///
+/// assert!(rhai_code.contains("defwindow(\"bar\""));
+/// ```
+pub fn transpile<'a>(
+    file_info: &'a FileInfo<'a>,
+    options: &TranspileOptions,
+) -> Result<Generated, TranspileError<'a>> {
+    let ast = parse(file_info)?;
+    let internal_tree = to_internal_tree(&ast, options)?;
+
+    // If any check failed, stop here
+    check(&internal_tree, options).map_err(TranspileError::Check)?;
+
+    if options.split_windows {
+        Ok(options.target.backend().generate_split(&internal_tree, options)?)
+    } else {
+        Ok(Generated { code: generate(&internal_tree, options)?, modules: Vec::new() })
+    }
+}
@@ -0,0 +1,74 @@
+//! Completion of elements, attributes, defines and variables.
+
+use super::Project;
+use super::text::{CursorContext, context_at, variables};
+use lsp_types::{CompletionItem, CompletionItemKind, Documentation, MarkupContent, MarkupKind};
+use xmlman::widgets::{WIDGETS, widget};
+
+pub fn completions(text: &str, offset: usize, project: &Project) -> Vec<CompletionItem> {
+    match context_at(text, offset) {
+        CursorContext::ElementName => {
+            let builtin = WIDGETS.iter().map(|w| CompletionItem {
+                label: w.name.to_string(),
+                kind: Some(CompletionItemKind::CLASS),
+                detail: Some(w.function.to_string()),
+                documentation: Some(markdown(w.documentation())),
+                ..Default::default()
+            });
+
+            let custom = project.options.widgets.iter().map(|(name, function)| CompletionItem {
+                label: name.clone(),
+                kind: Some(CompletionItemKind::CLASS),
+                detail: Some(format!("{} (custom widget)", function)),
+                ..Default::default()
+            });
+
+            builtin.chain(custom).collect()
+        }
+        CursorContext::AttributeName { element } => widget(element)
+            .into_iter()
+            .flat_map(|w| w.all_attributes())
+            .map(|attribute| CompletionItem {
+                label: attribute.to_string(),
+                kind: Some(CompletionItemKind::PROPERTY),
+                ..Default::default()
+            })
+            .collect(),
+        CursorContext::AttributeValue { before, .. } => {
+            if in_call(before, "@define(") {
+                project
+                    .options
+                    .defines
+                    .iter()
+                    .map(|(name, value)| CompletionItem {
+                        label: name.clone(),
+                        kind: Some(CompletionItemKind::CONSTANT),
+                        detail: Some(value.clone()),
+                        ..Default::default()
+                    })
+                    .collect()
+            } else if in_call(before, "@no_quote(") {
+                variables(text)
+                    .into_iter()
+                    .map(|(name, _)| CompletionItem {
+                        label: name.to_string(),
+                        kind: Some(CompletionItemKind::VARIABLE),
+                        ..Default::default()
+                    })
+                    .collect()
+            } else {
+                vec![]
+            }
+        }
+        CursorContext::Other => vec![],
+    }
+}
+
+/// Whether the end of `before` is inside the parentheses of `call`.
+pub fn in_call(before: &str, call: &str) -> bool {
+    before.rfind(call).is_some_and(|i| !before[i..].contains(')'))
+}
+
+pub fn markdown(value: String) -> Documentation {
+    Documentation::MarkupContent(MarkupContent { kind: MarkupKind::Markdown, value })
+}
@@ -0,0 +1,65 @@
+//! Diagnostics from running every transpile step on a document.
+
+use super::text::to_lsp_range;
+use lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString};
+use xmlman::{
+    CheckError, DiagInfo, FileInfo, TranspileOptions, check, generate, parse, to_internal_tree,
+};
+
+/// Transpile `text` and return the errors that were found.
+/// Like the transpiler, this stops at the first step that fails.
+pub fn diagnostics(text: &str, options: &TranspileOptions) -> Vec<Diagnostic> {
+    let file_info = FileInfo { file_path: "", script: text };
+
+    let ast = match parse(&file_info) {
+        Ok(ast) => ast,
+        Err(di) => return vec![from_diag_info(text, di)],
+    };
+
+    let tree = match to_internal_tree(&ast, options) {
+        Ok(tree) => tree,
+        Err(di) => return vec![from_diag_info(text, di)],
+    };
+
+    if let Err(errors) = check(&tree, options) {
+        return errors.iter().map(from_check_error).collect();
+    }
+
+    let code = match generate(&tree, options) {
+        Ok(code) => code,
+        Err(di) => return vec![from_diag_info(text, di)],
+    };
+
+    match options.target.backend().verify(&code) {
+        Ok(()) => vec![],
+        // The span points into the generated code, which the editor
+        // does not have, so the error is shown at the top instead.
+        Err(di) => vec![from_diag_info(text, DiagInfo { span: None, ..di })],
+    }
+}
+
+fn from_diag_info(text: &str, info: DiagInfo) -> Diagnostic {
+    let message = match info.note {
+        Some(note) => format!("{}\nnote: {}", info.message, note),
+        None => info.message,
+    };
+
+    Diagnostic {
+        range: to_lsp_range(text, info.span.unwrap_or(0..0)),
+        severity: Some(DiagnosticSeverity::ERROR),
+        code: Some(NumberOrString::String(info.code.to_string())),
+        source: Some("xmlman".to_string()),
+        message,
+        ..Default::default()
+    }
+}
+
+fn from_check_error(error: &CheckError) -> Diagnostic {
+    Diagnostic {
+        severity: Some(DiagnosticSeverity::ERROR),
+        code: Some(NumberOrString::String(error.code.to_string())),
+        source: Some("xmlman".to_string()),
+        message: error.message.to_string(),
+        ..Default::default()
+    }
+}
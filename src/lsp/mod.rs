@@ -0,0 +1,255 @@
+//! Language server for `xmlman lsp`.
+//!
+//! The server talks to the editor over stdio. Documents are synced in
+//! full on every change and transpiled right away to publish diagnostics.
+//! Every request loads the closest `xmlman.toml` again, so changes to
+//! the project config are picked up without restarting the server.
+
+mod completion;
+mod diagnostics;
+mod navigation;
+mod text;
+
+use log::{error, info};
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _,
+    PublishDiagnostics,
+};
+use lsp_types::request::{Completion, GotoDefinition, HoverRequest, Request as _};
+use lsp_types::{
+    CompletionOptions, CompletionResponse, Diagnostic, GotoDefinitionResponse,
+    HoverProviderCapability, Location, OneOf, Position, PublishDiagnosticsParams, Range,
+    ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use xmlman::TranspileOptions;
+use xmlman::config::{ProjectConfig, find_config};
+
+/// An open document and the diagnostics that were last published for it.
+pub struct Document {
+    pub text: String,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// The project that a document belongs to.
+pub struct Project {
+    pub options: TranspileOptions,
+    /// Path and content of the project's xmlman.toml, if there is one.
+    pub config: Option<(PathBuf, String)>,
+}
+
+impl Project {
+    /// Load the project from the closest xmlman.toml to the document.
+    /// An invalid config is treated like a missing one.
+    fn load(uri: &Url) -> Self {
+        let config = uri
+            .to_file_path()
+            .ok()
+            .and_then(|path| find_config(path.parent()?))
+            .and_then(|path| Some((fs::read_to_string(&path).ok()?, path)));
+
+        let Some((content, path)) = config else {
+            return Project { options: TranspileOptions::default(), config: None };
+        };
+
+        let options = ProjectConfig::parse(&path, &content)
+            .map(|config| config.transpile_options())
+            .unwrap_or_default();
+
+        Project { options, config: Some((path, content)) }
+    }
+
+    /// Location of `key` in the `[table]` of the project's xmlman.toml.
+    fn key_location(&self, table: &str, key: &str) -> Option<Location> {
+        let (path, content) = self.config.as_ref()?;
+        let header = format!("[{}]", table);
+        let mut in_table = false;
+
+        for (line_number, line) in content.lines().enumerate() {
+            let trimmed = line.trim();
+
+            if trimmed.starts_with('[') {
+                in_table = trimmed == header;
+                continue;
+            }
+
+            let Some((name, _)) = trimmed.split_once('=') else { continue };
+            if !in_table || name.trim().trim_matches(['"', '\'']) != key {
+                continue;
+            }
+
+            let start = line.len() - line.trim_start().len();
+            let line_number = line_number as u32;
+
+            return Some(Location {
+                uri: Url::from_file_path(path).ok()?,
+                range: Range {
+                    start: Position { line: line_number, character: start as u32 },
+                    end: Position { line: line_number, character: line.trim_end().len() as u32 },
+                },
+            });
+        }
+
+        None
+    }
+}
+
+/// Run the language server until the editor shuts it down.
+/// Returns `false` if the connection failed.
+pub fn run() -> bool {
+    let (connection, io_threads) = Connection::stdio();
+
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec!["<".into(), " ".into(), "(".into()]),
+            ..Default::default()
+        }),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        ..Default::default()
+    };
+
+    let capabilities =
+        serde_json::to_value(capabilities).expect("Failed to serialize capabilities");
+    if let Err(e) = connection.initialize(capabilities) {
+        error!("Failed to initialize the language server: {}", e);
+        return false;
+    }
+
+    info!("[-] Language server started");
+
+    let served = serve(&connection);
+
+    // The io threads only stop once the connection is gone
+    drop(connection);
+    io_threads.join().is_ok() && served
+}
+
+/// Handle messages until the editor asks the server to shut down.
+fn serve(connection: &Connection) -> bool {
+    let mut documents: HashMap<Url, Document> = HashMap::new();
+
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                match connection.handle_shutdown(&request) {
+                    Ok(true) => return true,
+                    Ok(false) => {}
+                    Err(e) => {
+                        error!("Failed to shut down the language server: {}", e);
+                        return false;
+                    }
+                }
+
+                let response = handle_request(request, &documents);
+                if connection.sender.send(Message::Response(response)).is_err() {
+                    return false;
+                }
+            }
+            Message::Notification(notification) => {
+                let Some(publish) = handle_notification(notification, &mut documents) else {
+                    continue;
+                };
+
+                let notification = Notification::new(PublishDiagnostics::METHOD.into(), publish);
+                if connection.sender.send(Message::Notification(notification)).is_err() {
+                    return false;
+                }
+            }
+            Message::Response(_) => {}
+        }
+    }
+
+    true
+}
+
+fn handle_request(request: Request, documents: &HashMap<Url, Document>) -> Response {
+    match request.method.as_str() {
+        Completion::METHOD => respond::<Completion>(request, |params| {
+            let position = params.text_document_position;
+            let uri = position.text_document.uri;
+            let document = documents.get(&uri)?;
+            let offset = text::position_to_offset(&document.text, position.position);
+
+            let items = completion::completions(&document.text, offset, &Project::load(&uri));
+            Some(CompletionResponse::Array(items))
+        }),
+        HoverRequest::METHOD => respond::<HoverRequest>(request, |params| {
+            let position = params.text_document_position_params;
+            let uri = position.text_document.uri;
+
+            navigation::hover(documents.get(&uri)?, position.position, &Project::load(&uri))
+        }),
+        GotoDefinition::METHOD => respond::<GotoDefinition>(request, |params| {
+            let position = params.text_document_position_params;
+            let uri = position.text_document.uri;
+            let document = documents.get(&uri)?;
+
+            navigation::definition(&uri, document, position.position, &Project::load(&uri))
+                .map(GotoDefinitionResponse::Scalar)
+        }),
+        _ => Response::new_err(
+            request.id,
+            ErrorCode::MethodNotFound as i32,
+            format!("Unsupported request: {}", request.method),
+        ),
+    }
+}
+
+/// Answer a request of type `R` with the result of `handler`.
+fn respond<R: lsp_types::request::Request>(
+    request: Request,
+    handler: impl FnOnce(R::Params) -> R::Result,
+) -> Response {
+    let id = request.id.clone();
+
+    match request.extract::<R::Params>(R::METHOD) {
+        Ok((id, params)) => Response::new_ok(id, handler(params)),
+        Err(e) => Response::new_err(id, ErrorCode::InvalidParams as i32, e.to_string()),
+    }
+}
+
+/// Keep the documents in sync with the editor.
+/// Returns the diagnostics to publish for the document that changed.
+fn handle_notification(
+    notification: Notification,
+    documents: &mut HashMap<Url, Document>,
+) -> Option<PublishDiagnosticsParams> {
+    let (uri, text) = match notification.method.as_str() {
+        DidOpenTextDocument::METHOD => {
+            let params: lsp_types::DidOpenTextDocumentParams =
+                serde_json::from_value(notification.params).ok()?;
+            (params.text_document.uri, params.text_document.text)
+        }
+        DidChangeTextDocument::METHOD => {
+            let params: lsp_types::DidChangeTextDocumentParams =
+                serde_json::from_value(notification.params).ok()?;
+            // Documents are synced in full, so the last change is the whole text
+            (params.text_document.uri, params.content_changes.into_iter().last()?.text)
+        }
+        DidCloseTextDocument::METHOD => {
+            let params: lsp_types::DidCloseTextDocumentParams =
+                serde_json::from_value(notification.params).ok()?;
+            documents.remove(&params.text_document.uri);
+
+            // Clear the diagnostics of the closed document
+            return Some(PublishDiagnosticsParams {
+                uri: params.text_document.uri,
+                diagnostics: vec![],
+                version: None,
+            });
+        }
+        _ => return None,
+    };
+
+    let project = Project::load(&uri);
+    let diagnostics = diagnostics::diagnostics(&text, &project.options);
+
+    documents.insert(uri.clone(), Document { text, diagnostics: diagnostics.clone() });
+
+    Some(PublishDiagnosticsParams { uri, diagnostics, version: None })
+}
@@ -0,0 +1,88 @@
+//! Hover docs and go to definition.
+
+use super::completion::in_call;
+use super::text::{
+    CursorContext, context_at, offset_to_position, position_to_offset, to_lsp_range, variables,
+    word_at,
+};
+use super::{Document, Project};
+use lsp_types::{
+    Hover, HoverContents, Location, MarkupContent, MarkupKind, NumberOrString, Position, Url,
+};
+use xmlman::explain::explanation;
+use xmlman::widgets::widget;
+
+/// Explain the error under the cursor, or document the element,
+/// define or variable that the cursor is on.
+pub fn hover(document: &Document, position: Position, project: &Project) -> Option<Hover> {
+    let text = &document.text;
+    let offset = position_to_offset(text, position);
+
+    let diagnostic = document.diagnostics.iter().find(|d| {
+        d.range.start <= position && (position < d.range.end || d.range.start == d.range.end)
+    });
+    if let Some(NumberOrString::String(code)) = diagnostic.and_then(|d| d.code.as_ref()) {
+        return explanation(code).map(|text| markdown_hover(text.to_string()));
+    }
+
+    let (range, word) = word_at(text, offset)?;
+
+    let value = match context_at(text, range.start) {
+        CursorContext::ElementName => match widget(word) {
+            Some(w) => w.documentation(),
+            None => {
+                let function = project.options.widgets.get(word)?;
+                format!("**`<{}>`** → `{}`\n\nCustom widget from xmlman.toml.", word, function)
+            }
+        },
+        CursorContext::AttributeValue { before, .. } if in_call(before, "@define(") => {
+            let value = project.options.defines.get(word)?;
+            format!("**`{}`** = `{}`\n\nDefine from xmlman.toml.", word, value)
+        }
+        CursorContext::AttributeValue { .. } => {
+            let (_, span) = variables(text).into_iter().find(|(name, _)| *name == word)?;
+            let line = offset_to_position(text, span.start).line + 1;
+            format!(
+                "**`{}`**\n\nVariable defined by the `{}>` on line {}.",
+                word, &text[span], line
+            )
+        }
+        _ => return None,
+    };
+
+    Some(markdown_hover(value))
+}
+
+/// Find where the custom widget, define or variable under the cursor is defined.
+/// Custom widgets and defines are defined in the project's xmlman.toml.
+pub fn definition(
+    uri: &Url,
+    document: &Document,
+    position: Position,
+    project: &Project,
+) -> Option<Location> {
+    let text = &document.text;
+    let offset = position_to_offset(text, position);
+    let (range, word) = word_at(text, offset)?;
+
+    match context_at(text, range.start) {
+        CursorContext::ElementName if widget(word).is_none() => {
+            project.key_location("widgets", word)
+        }
+        CursorContext::AttributeValue { before, .. } if in_call(before, "@define(") => {
+            project.key_location("defines", word)
+        }
+        CursorContext::AttributeValue { .. } => {
+            let (_, span) = variables(text).into_iter().find(|(name, _)| *name == word)?;
+            Some(Location { uri: uri.clone(), range: to_lsp_range(text, span) })
+        }
+        _ => None,
+    }
+}
+
+fn markdown_hover(value: String) -> Hover {
+    Hover {
+        contents: HoverContents::Markup(MarkupContent { kind: MarkupKind::Markdown, value }),
+        range: None,
+    }
+}
@@ -0,0 +1,214 @@
+//! Helpers to find out what is under the cursor in a document.
+//!
+//! The document is usually invalid while it is being edited, so
+//! everything here works on the raw text instead of the parsed tree.
+
+use lsp_types::Position;
+use std::ops::Range;
+use xmlparser::{Token, Tokenizer};
+
+/// What the cursor is on, as far as completion and navigation care.
+#[derive(Debug, PartialEq, Eq)]
+pub enum CursorContext<'a> {
+    /// The name of an element, after `<` or `</`.
+    ElementName,
+    /// An attribute name inside of the start tag of `element`.
+    AttributeName { element: &'a str },
+    /// The value of `attribute`. `before` is the part of the
+    /// value that comes before the cursor.
+    AttributeValue { element: &'a str, attribute: &'a str, before: &'a str },
+    /// Text, comments and everything else.
+    Other,
+}
+
+/// Find the context of the cursor at `offset`.
+pub fn context_at(text: &str, offset: usize) -> CursorContext<'_> {
+    let before = &text[..offset];
+
+    let Some(lt) = before.rfind('<') else { return CursorContext::Other };
+    let tag = &before[lt + 1..];
+
+    if tag.contains('>') || tag.starts_with(['!', '?']) {
+        return CursorContext::Other;
+    }
+
+    let Some(name_end) = tag.find(char::is_whitespace) else {
+        return CursorContext::ElementName;
+    };
+    let element = &tag[..name_end];
+    let rest = &tag[name_end..];
+
+    // Find out if the cursor is inside of a quoted value
+    let mut quote = None;
+    let mut value_start = 0;
+    for (i, c) in rest.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => {
+                quote = Some(c);
+                value_start = i + 1;
+            }
+            None => {}
+        }
+    }
+
+    if quote.is_some() {
+        let name = rest[..value_start - 1].trim_end().trim_end_matches('=').trim_end();
+        let attribute = name.rsplit(char::is_whitespace).next().unwrap_or_default();
+        return CursorContext::AttributeValue { element, attribute, before: &rest[value_start..] };
+    }
+
+    match rest.rsplit(char::is_whitespace).next() {
+        Some(word) if word.contains('=') => CursorContext::Other,
+        _ => CursorContext::AttributeName { element },
+    }
+}
+
+/// The word at `offset`, and where it is in `text`.
+pub fn word_at(text: &str, offset: usize) -> Option<(Range<usize>, &str)> {
+    let is_word = |c: char| c.is_alphanumeric() || matches!(c, '_' | '-' | '.');
+
+    let start = text[..offset].rfind(|c| !is_word(c)).map_or(0, |i| i + 1);
+    let end = text[offset..].find(|c| !is_word(c)).map_or(text.len(), |i| offset + i);
+
+    (start < end).then(|| (start..end, &text[start..end]))
+}
+
+/// The `<Poll>` and `<Listen>` variables defined in `text`,
+/// with the span of the element that defines them.
+pub fn variables(text: &str) -> Vec<(&str, Range<usize>)> {
+    let mut variables = Vec::new();
+    let mut element = None;
+
+    // Stops at the first error, so that the variables
+    // before it can still be found while editing.
+    for token in Tokenizer::from(text).map_while(|t| t.ok()) {
+        match token {
+            Token::ElementStart { local, span, .. } => {
+                element = matches!(local.as_str(), "Poll" | "Listen").then(|| span.range());
+            }
+            Token::Attribute { local, value, .. } if local.as_str() == "name" => {
+                if let Some(span) = &element {
+                    variables.push((value.as_str(), span.clone()));
+                }
+            }
+            Token::ElementEnd { .. } => element = None,
+            _ => {}
+        }
+    }
+
+    variables
+}
+
+/// Convert a byte offset into an LSP position, which counts UTF-16 code units.
+pub fn offset_to_position(text: &str, offset: usize) -> Position {
+    let offset = offset.min(text.len());
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+
+    Position {
+        line: before.matches('\n').count() as u32,
+        character: before[line_start..].encode_utf16().count() as u32,
+    }
+}
+
+/// Convert an LSP position into a byte offset.
+pub fn position_to_offset(text: &str, position: Position) -> usize {
+    let line_start = match position.line {
+        0 => 0,
+        n => text.match_indices('\n').nth(n as usize - 1).map_or(text.len(), |(i, _)| i + 1),
+    };
+
+    let mut utf16 = 0;
+    for (i, c) in text[line_start..].char_indices() {
+        if utf16 >= position.character as usize || c == '\n' {
+            return line_start + i;
+        }
+        utf16 += c.len_utf16();
+    }
+
+    text.len()
+}
+
+/// Convert a byte range into an LSP range.
+pub fn to_lsp_range(text: &str, range: Range<usize>) -> lsp_types::Range {
+    lsp_types::Range {
+        start: offset_to_position(text, range.start),
+        end: offset_to_position(text, range.end),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(line: u32, character: u32) -> Position {
+        Position { line, character }
+    }
+
+    #[test]
+    fn positions_count_utf16_code_units() {
+        // The emoji is 4 bytes in UTF-8 and 2 code units in UTF-16
+        let text = "<Root>\n  <Label text=\"😀 é\"/>\n</Root>";
+        let line_start = text.find("  <Label").unwrap();
+        let e_acute = text.find('é').unwrap();
+
+        assert_eq!(position_to_offset(text, position(0, 0)), 0);
+        assert_eq!(position_to_offset(text, position(1, 0)), line_start);
+        assert_eq!(position_to_offset(text, position(1, 17)), e_acute - 1);
+        assert_eq!(position_to_offset(text, position(1, 18)), e_acute);
+        assert_eq!(offset_to_position(text, e_acute), position(1, 18));
+
+        // Positions past the end of a line or of the text are clamped
+        assert_eq!(position_to_offset(text, position(0, 99)), text.find('\n').unwrap());
+        assert_eq!(position_to_offset(text, position(9, 0)), text.len());
+
+        for offset in [0, line_start, e_acute, text.len()] {
+            let position = offset_to_position(text, offset);
+            assert_eq!(position_to_offset(text, position), offset);
+        }
+    }
+
+    #[test]
+    fn cursor_context() {
+        let text = "<Root>\n  <Label text=\"😀 hi\" />\n  <!-- comment -->\n</Root>";
+        let at = |needle: &str| text.find(needle).unwrap() + needle.len();
+
+        assert_eq!(context_at(text, at("<La")), CursorContext::ElementName);
+        assert_eq!(context_at(text, at("</Ro")), CursorContext::ElementName);
+        assert_eq!(
+            context_at(text, at("<Label te")),
+            CursorContext::AttributeName { element: "Label" }
+        );
+        assert_eq!(
+            context_at(text, at("\"😀 hi\" ")),
+            CursorContext::AttributeName { element: "Label" }
+        );
+        assert_eq!(
+            context_at(text, at("😀 h")),
+            CursorContext::AttributeValue { element: "Label", attribute: "text", before: "😀 h" }
+        );
+        assert_eq!(context_at(text, at("text=")), CursorContext::Other);
+        assert_eq!(context_at(text, at("<!-- ")), CursorContext::Other);
+        assert_eq!(context_at(text, at("<Root>\n")), CursorContext::Other);
+    }
+
+    #[test]
+    fn variables_are_found_before_errors() {
+        let text = concat!(
+            "<Root>\n",
+            "  <Poll name=\"time\" cmd=\"date\"/>\n",
+            "  <Label name=\"label\"/>\n",
+            "  <Listen cmd=\"x\" name=\"music\"/>\n",
+            "  <Poll name=\"after\" <\n",
+            "  <Poll name=\"broken\"/>\n",
+        );
+
+        let names: Vec<_> = variables(text).into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["time", "music", "after"]);
+
+        let (_, span) = &variables(text)[0];
+        assert_eq!(&text[span.clone()], "<Poll");
+    }
+}
@@ -1,107 +1,645 @@
-mod checks;
-mod error;
+mod init;
+mod lsp;
 mod opts;
-mod parser;
-mod transpiler;
+mod report;
+mod watch;
 
-use checks::run_all_checks;
-use error::print_diag_error;
-use opts::XmlManArgs;
-use parser::parse_xml;
-use transpiler::{convert_node, convert_tree};
+use opts::{AstStage, Command, ImportFormat, MessageFormat, SchemaFormat, XmlManArgs};
+use report::{Report, run_jobs};
+use xmlman::cache::BuildCache;
+use xmlman::checks::report_lines;
+use xmlman::config::{ProjectConfig, find_config};
+use xmlman::dump::{ast_json, tree_json};
+use xmlman::error::DiagInfo;
+use xmlman::explain::explanation;
+use xmlman::formatter::{FmtOptions, format_xml};
+use xmlman::import::{import_rhai, import_yuck};
+use xmlman::inputs::{OutputClaims, STDIN, resolve_inputs};
+use xmlman::schema::to_xsd;
+use xmlman::{
+    FileInfo, Generated, InputFile, TranspileError, TranspileOptions, parse, to_internal_tree,
+    transpile,
+};
 
 use clap::Parser as ClapParser;
 use colored::Colorize;
-use log::{Level, error, info};
+use log::{Level, error};
 use std::fs;
+use std::io::{self, Read};
 use std::path::{Path, PathBuf};
-
-/// [`FileInfo`] is structure for holding both the
-/// file_path and xml content. It is used to send
-/// info to the transpiler so that it can print pretty
-/// errors whenever it need to.
-pub struct FileInfo<'a> {
-    file_path: &'a str,
-    script: &'a str,
-}
+use std::process;
 
 fn main() {
-    let args = XmlManArgs::parse();
+    let mut args = XmlManArgs::parse();
 
     set_debug_levels(args.debug);
 
-    // This is where we start transpiling to rhai.
-    // Full transpile process:
-    //
-    // -----------      ---------------------------------
-    // | Read fs | ---> | Parse with xml-rs & xmlparser | ---->
-    // -----------      ---------------------------------     |
-    //                              ---------------------------------------------------
-    //              --------------- | Convert XML AST to internal tree representation |
-    //              | [2nd]         ---------------------------------------------------
-    //              |                                                 | [1st]
-    //     ----------------------------------------                   |
-    //     | Internal tree then converted to Rhai |    ----------------------------------
-    //     ----------------------------------------    | Run checks to prevent mistakes |
-    //              |                                  ----------------------------------
-    //     ------------------------
-    //     | Optionally formatted |
-    //     ------------------------
-    //              |
-    //              |      ---------------
-    //              | ---> | Write to fs |
-    //                     ---------------
-    //
-    for file in args.files {
-        if !fs::exists(&file).expect("Could not check file existence") {
-            error!("The file '{}' does not exist.", &file);
-            return;
-        }
-
-        let xml_content = fs::read_to_string(&file).expect("Failed to read file");
-
-        let file_info = FileInfo { file_path: &file, script: &xml_content };
-
-        match parse_xml(&file_info) {
-            Ok(ast) => {
-                // convert to internal tree
-                // the internal tree is a tree that
-                // stands between xml and rhai.
-                let internal_tree = match convert_node(ast, &file_info) {
-                    Ok(t) => t,
-                    Err(di) => {
-                        print_diag_error(Some(&file), &xml_content, di);
-                        return;
-                    }
-                };
+    let command = args.command();
+    let output = OutputOptions { to_stdout: args.stdout, message_format: args.message_format };
+
+    let config = match command {
+        Command::Explain { .. } | Command::Init { .. } | Command::Lsp => None,
+        _ => load_project_config(&output),
+    };
+
+    let mut options = config.as_ref().map(|c| c.transpile_options()).unwrap_or_default();
+
+    // Flags passed on the command line override the project config
+    if let Some(out) = args.out.as_deref() {
+        options.out = Some(out.into());
+    }
+    if let Some(target) = args.target {
+        options.target = target.into();
+    }
+    if let Some(split_windows) = args.split_windows {
+        options.split_windows = split_windows;
+    }
+    args.rhai_style.apply(&mut options.rhai_style);
+
+    match command {
+        Command::Ast { file, stage } => {
+            let input = match file.as_str() {
+                STDIN => InputFile::stdin(args.stdin_filename.as_deref()),
+                file => InputFile::from_file(file),
+            };
+
+            // The dump is always printed
+            let output = OutputOptions { to_stdout: true, ..output };
+
+            let mut report = Report::default();
+            let dumped = dump_file(&input, stage, &options, &output, &mut report);
+            report.print();
+
+            if !dumped {
+                process::exit(1);
+            }
+        }
+        Command::Build { files } => {
+            let files = resolve_files(
+                &input_patterns(files, config.as_ref(), &output),
+                &options,
+                &output,
+                &args,
+            );
+            let cache = build_cache(config.as_ref(), &args);
+            let claims = output_claims(&files, &options);
+
+            let failed = run_jobs(&files, args.jobs(), |file, report| {
+                transpile_file(file, &options, &output, cache.as_ref(), Some(&claims), report)
+            });
+
+            save_build_cache(cache.as_ref(), &output);
+
+            if failed > 0 {
+                error!("{} of {} files failed to transpile.", failed, files.len());
+                process::exit(1);
+            }
+        }
+        Command::Check { files } => {
+            let files = resolve_files(
+                &input_patterns(files, config.as_ref(), &output),
+                &options,
+                &output,
+                &args,
+            );
+            let failed = run_jobs(&files, args.jobs(), |file, report| {
+                check_file(file, &options, &output, report)
+            });
+
+            if failed > 0 {
+                error!("{} of {} files failed the check.", failed, files.len());
+                process::exit(1);
+            }
+        }
+        Command::Explain { code } => match explanation(&code) {
+            Some(text) => println!("{}", text),
+            None => {
+                error!("'{}' is not a valid error code.", code);
+                process::exit(1);
+            }
+        },
+        Command::Fmt { files, check, wrap_attributes } => {
+            let files = resolve_files(
+                &input_patterns(files, config.as_ref(), &output),
+                &options,
+                &output,
+                &args,
+            );
 
-                // If any check failed, return;
-                if let Err(_) = run_all_checks(&internal_tree) {
-                    return;
+            let mut fmt_options = config.as_ref().map(|c| c.fmt_options()).unwrap_or_default();
+            if let Some(wrap_attributes) = wrap_attributes {
+                fmt_options.wrap_attributes = wrap_attributes;
+            }
+            let failed = run_jobs(&files, args.jobs(), |file, report| {
+                format_file(file, check, &fmt_options, &output, report)
+            });
+
+            if failed > 0 {
+                if check {
+                    error!("{} of {} files are not formatted.", failed, files.len());
                 }
+                process::exit(1);
+            }
+        }
+        Command::Import { file, from } => {
+            let input = match file.as_str() {
+                STDIN => InputFile::stdin(args.stdin_filename.as_deref()),
+                file => InputFile::from_file(file),
+            };
+            let from = from.unwrap_or(match input.path.extension() {
+                Some(ext) if ext == "yuck" => ImportFormat::Yuck,
+                _ => ImportFormat::Rhai,
+            });
+            let fmt_options = config.as_ref().map(|c| c.fmt_options()).unwrap_or_default();
 
-                let transpiled_code = match convert_tree(&internal_tree) {
-                    Ok(c) => c,
-                    Err(di) => {
-                        print_diag_error(Some(&file), &xml_content, di);
-                        return;
-                    }
-                };
+            let mut report = Report::default();
+            let imported = import_file(
+                &input,
+                from,
+                args.out.as_deref(),
+                &options,
+                &fmt_options,
+                &output,
+                &mut report,
+            );
+            report.print();
+
+            if !imported {
+                process::exit(1);
+            }
+        }
+        Command::Init { dir } => {
+            if !init::init(dir.as_deref().unwrap_or(".")) {
+                process::exit(1);
+            }
+        }
+        Command::Lsp => {
+            if !lsp::run() {
+                process::exit(1);
+            }
+        }
+        Command::Schema { format } => match format {
+            SchemaFormat::Xsd => print!("{}", to_xsd(&options.widgets)),
+        },
+        Command::Watch { paths } => {
+            let inputs = input_patterns(paths, config.as_ref(), &output);
+            let cache = build_cache(config.as_ref(), &args);
+            watch::watch(&inputs, &options, &output, cache.as_ref(), args.jobs());
+        }
+    }
+}
 
-                let file_name = Path::new(&file).file_stem().unwrap().to_str().unwrap();
+/// Where the output of a command goes, and how diagnostics are printed.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct OutputOptions {
+    /// Print the output instead of writing it to files.
+    pub to_stdout: bool,
+    pub message_format: MessageFormat,
+}
+
+/// Load the `xmlman.toml` of the project that the current directory is in.
+/// Exits if the config file is invalid.
+fn load_project_config(output: &OutputOptions) -> Option<ProjectConfig> {
+    let cwd = std::env::current_dir().ok()?;
+    let path = find_config(&cwd)?;
+    let content = fs::read_to_string(&path).expect("Failed to read project config");
+
+    match ProjectConfig::parse(&path, &content) {
+        Ok(config) => Some(config),
+        Err(di) => {
+            let mut report = Report::default();
+            report.diag(output, &display_path(&path), &content, di);
+            report.print();
+            process::exit(1);
+        }
+    }
+}
+
+/// The inputs passed on the command line, or the inputs
+/// of the project config if no input was passed.
+fn input_patterns(
+    inputs: Vec<String>,
+    config: Option<&ProjectConfig>,
+    output: &OutputOptions,
+) -> Vec<String> {
+    if !inputs.is_empty() {
+        return inputs;
+    }
+
+    let Some(config) = config else {
+        exit_with_error(
+            output,
+            "No files were given, and no xmlman.toml was found to read the inputs from.".into(),
+        );
+    };
+
+    let patterns = config.input_patterns();
+    if patterns.is_empty() {
+        exit_with_error(
+            output,
+            "No files were given, and the xmlman.toml does not have any inputs.".into(),
+        );
+    }
+
+    patterns.iter().map(|p| display_path(Path::new(p))).collect()
+}
+
+/// Resolve the input files, directories and glob patterns into xml files.
+/// Exits if any of them is invalid.
+fn resolve_files(
+    patterns: &[String],
+    options: &TranspileOptions,
+    output: &OutputOptions,
+    args: &XmlManArgs,
+) -> Vec<InputFile> {
+    match resolve_inputs(patterns, args.stdin_filename.as_deref(), options) {
+        Ok(files) => files,
+        Err(e) => exit_with_error(output, e),
+    }
+}
+
+/// Print an error that stops the whole command, and exit.
+fn exit_with_error(output: &OutputOptions, message: String) -> ! {
+    let mut report = Report::default();
+    report.fail(output, None, message);
+    report.print();
+    process::exit(1);
+}
+
+/// The build cache of the project. Files are only cached inside
+/// of a project, so that no cache is left next to a single file.
+fn build_cache(config: Option<&ProjectConfig>, args: &XmlManArgs) -> Option<BuildCache> {
+    config.filter(|_| !args.no_cache).map(|c| BuildCache::load(&c.cache_dir()))
+}
 
-                let out_path: PathBuf = if let Some(out_dir) = args.out.as_deref() {
-                    Path::new(out_dir).join(format!("{}.rhai", file_name))
-                } else {
-                    PathBuf::from(format!("{}.rhai", file_name))
-                };
+pub(crate) fn save_build_cache(cache: Option<&BuildCache>, output: &OutputOptions) {
+    if let Some(Err(e)) = cache.map(|c| c.save()) {
+        let mut report = Report::default();
+        report.fail(output, None, format!("Failed to save the build cache: {}", e));
+        report.print();
+    }
+}
+
+/// Show paths relative to the current directory when possible,
+/// as they are a lot easier to read in diagnostics.
+pub(crate) fn display_path(path: &Path) -> String {
+    let relative = std::env::current_dir()
+        .ok()
+        .and_then(|cwd| path.strip_prefix(cwd).ok().map(|p| p.to_path_buf()));
+
+    relative.as_deref().unwrap_or(path).to_string_lossy().into_owned()
+}
+
+/// Claims of the output paths of `files`, so that the modules
+/// of split windows can't overwrite another file.
+pub(crate) fn output_claims(files: &[InputFile], options: &TranspileOptions) -> OutputClaims {
+    OutputClaims::new(files.iter().map(|f| (options.output_path(f), f.path.clone())))
+}
+
+/// Transpile a single file and write the result to its output path.
+/// Returns `false` if the file could not be transpiled.
+/// Files that are up to date in `cache` are skipped, and the modules
+/// of split windows must not be claimed by another file in `claims`.
+pub(crate) fn transpile_file(
+    input: &InputFile,
+    options: &TranspileOptions,
+    output: &OutputOptions,
+    cache: Option<&BuildCache>,
+    claims: Option<&OutputClaims>,
+    report: &mut Report,
+) -> bool {
+    let file = &input.path.to_string_lossy();
+    let Some(xml_content) = read_input(input, output, report) else { return false };
+
+    let out_path = options.output_path(input);
+    // Stdin and stdout are never cached, and neither are the
+    // modules of split windows, as they are not known up front
+    let cache = cache.filter(|_| !input.from_stdin && !output.to_stdout && !options.split_windows);
+
+    if cache.is_some_and(|c| c.is_up_to_date(&out_path, &xml_content, options)) {
+        report.info(format!("[-] '{}' is up to date", file));
+        return true;
+    }
+
+    let Some(generated) = transpile_content(input, &xml_content, options, output, report) else {
+        return false;
+    };
+    let transpiled_code = generated.code;
+
+    if output.to_stdout {
+        if !generated.modules.is_empty() {
+            let message =
+                format!("'{}' has a file for each window, which --stdout can't print", file);
+            report.fail(output, Some(file), message);
+            return false;
+        }
+
+        report.stdout(transpiled_code);
+        return true;
+    }
+
+    // writing transpiled code, and the modules next to it
+    let out_dir = out_path.parent().unwrap_or(Path::new(""));
+    let modules: Vec<(PathBuf, &String)> =
+        generated.modules.iter().map(|(path, code)| (out_dir.join(path), code)).collect();
 
-                // writing transpiled code
-                fs::write(&out_path, transpiled_code).expect("Failed to write transpiled file");
+    if let Some(claims) = claims {
+        let paths = std::iter::once(&out_path).chain(modules.iter().map(|(path, _)| path));
 
-                info!("[-] Transpiled '{}' to '{}'", &file_name, &out_path.display())
+        for path in paths {
+            if let Err(e) = claims.claim(path, &input.path) {
+                report.fail(output, Some(file), e);
+                return false;
+            }
+        }
+    }
+
+    for (module_path, code) in modules {
+        if !write_output(&module_path, code, input, output, report) {
+            return false;
+        }
+    }
+
+    if !write_output(&out_path, &transpiled_code, input, output, report) {
+        return false;
+    }
+
+    if let Some(cache) = cache {
+        cache.record(&out_path, &xml_content, options, &transpiled_code);
+    }
+
+    report.info(format!("[-] Transpiled '{}' to '{}'", file, out_path.display()));
+    true
+}
+
+/// Run every step of a transpile on a single file, including compiling
+/// the generated code, without writing anything to disk.
+/// Returns `false` if any error was found.
+fn check_file(
+    input: &InputFile,
+    options: &TranspileOptions,
+    output: &OutputOptions,
+    report: &mut Report,
+) -> bool {
+    let file = &input.path.to_string_lossy();
+    let Some(xml_content) = read_input(input, output, report) else { return false };
+    let Some(generated) = transpile_content(input, &xml_content, options, output, report) else {
+        return false;
+    };
+
+    let mut valid = true;
+    let modules = generated.modules.iter().map(|(path, code)| (Some(path), code));
+
+    for (path, code) in std::iter::once((None, &generated.code)).chain(modules) {
+        if let Err(di) = options.target.backend().verify(code) {
+            let name = match path {
+                Some(path) => format!("{} (generated {})", file, path.display()),
+                None => format!("{} (generated)", file),
+            };
+            report.diag(output, &name, code, di);
+            valid = false;
+        }
+    }
+
+    if !valid {
+        return false;
+    }
+
+    report.info(format!("[-] '{}' has no errors", file));
+    true
+}
+
+/// Format a single file in place. Input read from stdin is always printed.
+/// In check mode, the file is left as it is and `false`
+/// is returned if it is not formatted.
+fn format_file(
+    input: &InputFile,
+    check: bool,
+    fmt_options: &FmtOptions,
+    output: &OutputOptions,
+    report: &mut Report,
+) -> bool {
+    // Stdin is always printed
+    let output = &OutputOptions { to_stdout: output.to_stdout || input.from_stdin, ..*output };
+
+    let file = &input.path.to_string_lossy();
+    let Some(xml_content) = read_input(input, output, report) else { return false };
+
+    let file_info = FileInfo { file_path: file, script: &xml_content };
+
+    let formatted = match format_xml(&file_info, fmt_options) {
+        Ok(f) => f,
+        Err(di) => {
+            report.diag(output, file, &xml_content, di);
+            return false;
+        }
+    };
+
+    if check {
+        if formatted != xml_content {
+            report.fail(output, Some(file), format!("'{}' is not formatted.", file));
+            return false;
+        }
+        return true;
+    }
+
+    if output.to_stdout {
+        report.stdout(formatted);
+        return true;
+    }
+
+    if formatted == xml_content {
+        return true;
+    }
+
+    if !write_output(&input.path, &formatted, input, output, report) {
+        return false;
+    }
+
+    report.info(format!("[-] Formatted '{}'", file));
+    true
+}
+
+/// Print a stage of the transpiler for a single file as JSON.
+fn dump_file(
+    input: &InputFile,
+    stage: AstStage,
+    options: &TranspileOptions,
+    output: &OutputOptions,
+    report: &mut Report,
+) -> bool {
+    let file = &input.path.to_string_lossy();
+    let Some(xml_content) = read_input(input, output, report) else { return false };
+
+    let file_info = FileInfo { file_path: file, script: &xml_content };
+
+    let ast = match parse(&file_info) {
+        Ok(ast) => ast,
+        Err(di) => {
+            report.diag(output, file, &xml_content, di);
+            return false;
+        }
+    };
+
+    let json = match stage {
+        AstStage::Parse => ast_json(&file_info, &ast),
+        AstStage::Tree => match to_internal_tree(&ast, options) {
+            Ok(tree) => tree_json(&file_info, &tree),
+            Err(di) => {
+                report.diag(output, file, &xml_content, di);
+                return false;
+            }
+        },
+    };
+
+    report.stdout(format!("{}\n", json));
+    true
+}
+
+/// Import a Rhai or yuck config as xml. The xml is written next to the
+/// config, or to `out` if it is given. Existing files are not overwritten.
+fn import_file(
+    input: &InputFile,
+    from: ImportFormat,
+    out: Option<&str>,
+    options: &TranspileOptions,
+    fmt_options: &FmtOptions,
+    output: &OutputOptions,
+    report: &mut Report,
+) -> bool {
+    // Stdin is always printed
+    let output = &OutputOptions { to_stdout: output.to_stdout || input.from_stdin, ..*output };
+
+    let file = &input.path.to_string_lossy();
+    let Some(script) = read_input(input, output, report) else { return false };
+
+    let file_info = FileInfo { file_path: file, script: &script };
+
+    let imported = match from {
+        ImportFormat::Rhai => import_rhai(&file_info, options, fmt_options),
+        ImportFormat::Yuck => import_yuck(&file_info, options, fmt_options),
+    };
+
+    let xml = match imported {
+        Ok(imported) => {
+            for warning in imported.warnings {
+                report.warn(format!("'{}': {}", file, warning));
+            }
+            imported.xml
+        }
+        Err(errors) => {
+            for di in errors {
+                report.diag(output, file, &script, di);
+            }
+            return false;
+        }
+    };
+
+    if output.to_stdout {
+        report.stdout(xml);
+        return true;
+    }
+
+    let out_path = match out {
+        Some(out_dir) => Path::new(out_dir).join(input.relative.with_extension("xml")),
+        None => input.path.with_extension("xml"),
+    };
+
+    if out_path.exists() {
+        report.fail(output, Some(file), format!("'{}' already exists.", out_path.display()));
+        return false;
+    }
+
+    if !write_output(&out_path, &xml, input, output, report) {
+        return false;
+    }
+
+    report.info(format!("[-] Imported '{}' to '{}'", file, out_path.display()));
+    true
+}
+
+/// Read the content of an input, from stdin if it is `-`.
+fn read_input(input: &InputFile, output: &OutputOptions, report: &mut Report) -> Option<String> {
+    let file = input.path.to_string_lossy();
+
+    if input.from_stdin {
+        let mut content = String::new();
+        if let Err(e) = io::stdin().read_to_string(&mut content) {
+            report.fail(output, Some(&file), format!("Failed to read from stdin: {}", e));
+            return None;
+        }
+        return Some(content);
+    }
+
+    match fs::read_to_string(&input.path) {
+        Ok(content) => Some(content),
+        Err(_) => {
+            report.fail(output, Some(&file), format!("The file '{}' does not exist.", file));
+            None
+        }
+    }
+}
+
+/// Write `content` to `path`, creating the directories that it is in.
+/// Reports an error if it could not be written.
+fn write_output(
+    path: &Path,
+    content: &str,
+    input: &InputFile,
+    output: &OutputOptions,
+    report: &mut Report,
+) -> bool {
+    let written = match path.parent() {
+        Some(parent) => fs::create_dir_all(parent).and_then(|_| fs::write(path, content)),
+        None => fs::write(path, content),
+    };
+
+    if let Err(e) = written {
+        let message = format!("Failed to write '{}': {}", path.display(), e);
+        report.fail(output, Some(&input.path.to_string_lossy()), message);
+        return false;
+    }
+    true
+}
+
+/// Transpile the content of an input, printing any errors that are found.
+fn transpile_content(
+    input: &InputFile,
+    xml_content: &str,
+    options: &TranspileOptions,
+    output: &OutputOptions,
+    report: &mut Report,
+) -> Option<Generated> {
+    let file = &input.path.to_string_lossy();
+    let file_info = FileInfo { file_path: file, script: xml_content };
+
+    // This is where we start transpiling to rhai.
+    // See the `xmlman` crate docs for the full transpile process.
+    match transpile(&file_info, options) {
+        Ok(c) => Some(c),
+        Err(TranspileError::Diag(di)) => {
+            report.diag(output, file, xml_content, di);
+            None
+        }
+        Err(TranspileError::Check(errors)) => {
+            match output.message_format {
+                MessageFormat::Human => {
+                    report_lines(&errors).into_iter().for_each(|l| report.error(l))
+                }
+                MessageFormat::Json => {
+                    for e in errors {
+                        let di = DiagInfo {
+                            code: e.code,
+                            message: e.message.to_string(),
+                            label: None,
+                            note: None,
+                            span: None,
+                        };
+                        report.diag(output, file, xml_content, di);
+                    }
+                }
             }
-            Err(_) => return,
+            None
         }
     }
 }
@@ -1,18 +1,261 @@
-use clap::Parser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::num::NonZeroUsize;
+use xmlman::{Indent, RhaiStyle, Target};
 
 /// XmlMan: An elegant xml to rhai transpiler for ewwii.
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
-#[command(arg_required_else_help = true)]
+#[command(arg_required_else_help = true, args_conflicts_with_subcommands = true)]
 pub struct XmlManArgs {
-    /// Files to transpile.
-    pub files: Vec<String>,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Files to transpile, or `-` for stdin. Same as `xmlman build <FILES>`.
+    files: Vec<String>,
 
     /// Path to output the transpiled files.
-    #[clap(short, long)]
+    #[clap(short, long, global = true)]
     pub out: Option<String>,
 
+    /// Print the output to stdout instead of writing it to files.
+    #[arg(long, global = true)]
+    pub stdout: bool,
+
+    /// Name of the file read from stdin (`-`), shown in diagnostics.
+    #[arg(long, global = true)]
+    pub stdin_filename: Option<String>,
+
+    /// Transpile every file, even the ones that are up to date.
+    #[arg(long, global = true)]
+    pub no_cache: bool,
+
+    /// Number of files to process in parallel.
+    /// Defaults to the number of CPUs.
+    #[arg(short, long, global = true)]
+    jobs: Option<NonZeroUsize>,
+
+    /// The config to transpile to. Defaults to rhai.
+    #[arg(long, global = true, value_enum)]
+    pub target: Option<CodegenTarget>,
+
+    /// Write each window to its own module in `windows/`,
+    /// imported by the transpiled file.
+    /// Pass `--split-windows=false` to turn it off.
+    #[arg(
+        long,
+        global = true,
+        value_name = "BOOL",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    pub split_windows: Option<bool>,
+
+    #[command(flatten)]
+    pub rhai_style: RhaiStyleArgs,
+
+    /// How diagnostics are printed.
+    #[arg(long, global = true, value_enum, default_value_t = MessageFormat::Human)]
+    pub message_format: MessageFormat,
+
     /// Show debug logs.
-    #[arg(long)]
+    #[arg(long, global = true)]
     pub debug: bool,
 }
+
+/// Flags that override the `[rhai]` table of xmlman.toml.
+#[derive(Args, Debug)]
+#[command(next_help_heading = "Rhai style")]
+pub struct RhaiStyleArgs {
+    /// Number of spaces per indentation level, or `tab`.
+    #[arg(long, global = true)]
+    rhai_indent: Option<Indent>,
+
+    /// Maximum line width before maps are expanded.
+    #[arg(long, global = true)]
+    rhai_max_width: Option<usize>,
+
+    /// Put a comma after the last entry of expanded maps and arrays.
+    /// Pass `--rhai-trailing-commas=false` to turn them off.
+    #[arg(
+        long,
+        global = true,
+        value_name = "BOOL",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    rhai_trailing_commas: Option<bool>,
+
+    /// Number of blank lines between functions.
+    #[arg(long, global = true)]
+    rhai_blank_lines: Option<usize>,
+
+    /// Generate Rhai without optional whitespace or long function names.
+    /// Pass `--minify=false` to turn it off.
+    #[arg(
+        long,
+        global = true,
+        value_name = "BOOL",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    minify: Option<bool>,
+}
+
+impl RhaiStyleArgs {
+    /// Override `style` with the flags that were passed.
+    pub fn apply(&self, style: &mut RhaiStyle) {
+        style.indent = self.rhai_indent.unwrap_or(style.indent);
+        style.max_width = self.rhai_max_width.or(style.max_width);
+        style.trailing_commas = self.rhai_trailing_commas.unwrap_or(style.trailing_commas);
+        style.blank_lines = self.rhai_blank_lines.unwrap_or(style.blank_lines);
+        style.minify = self.minify.unwrap_or(style.minify);
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MessageFormat {
+    /// Colored reports for the terminal.
+    #[default]
+    Human,
+    /// One JSON object per line on stdout.
+    Json,
+}
+
+impl XmlManArgs {
+    /// Number of threads to process files with.
+    pub fn jobs(&self) -> usize {
+        self.jobs.or_else(|| std::thread::available_parallelism().ok()).map_or(1, NonZeroUsize::get)
+    }
+
+    /// The subcommand to run.
+    /// `xmlman <FILES>` is an alias for `xmlman build <FILES>`.
+    pub fn command(&mut self) -> Command {
+        self.command.take().unwrap_or_else(|| Command::Build { files: self.files.split_off(0) })
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaFormat {
+    /// XML Schema Definition.
+    Xsd,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodegenTarget {
+    /// Rhai scripts for ewwii.
+    Rhai,
+    /// Yuck configs for eww.
+    Yuck,
+}
+
+impl From<CodegenTarget> for Target {
+    fn from(target: CodegenTarget) -> Self {
+        match target {
+            CodegenTarget::Rhai => Target::Rhai,
+            CodegenTarget::Yuck => Target::Yuck,
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AstStage {
+    /// The parsed xml elements.
+    Parse,
+    /// The internal tree that rhai is generated from.
+    Tree,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    /// An ewwii config written in Rhai.
+    Rhai,
+    /// An eww config written in yuck.
+    Yuck,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Print the parsed xml or the internal tree of a file as JSON.
+    Ast {
+        /// The xml file to dump, or `-` for stdin.
+        file: String,
+
+        /// Which stage of the transpiler to dump.
+        #[arg(long, value_enum, default_value_t = AstStage::Tree)]
+        stage: AstStage,
+    },
+
+    /// Transpile files to rhai.
+    Build {
+        /// Files to transpile, or `-` for stdin. Defaults to the inputs in xmlman.toml.
+        files: Vec<String>,
+    },
+
+    /// Check files for errors without writing any output.
+    Check {
+        /// Files to check, or `-` for stdin. Defaults to the inputs in xmlman.toml.
+        files: Vec<String>,
+    },
+
+    /// Format xml files.
+    Fmt {
+        /// Files to format, or `-` for stdin. Defaults to the inputs in xmlman.toml.
+        files: Vec<String>,
+
+        /// Don't write the files, exit with an error if any of them is not formatted.
+        #[arg(long)]
+        check: bool,
+
+        /// Put each attribute on its own line when a start tag is too long.
+        /// Pass `--wrap-attributes=false` to turn it off.
+        #[arg(
+            long,
+            value_name = "BOOL",
+            num_args = 0..=1,
+            require_equals = true,
+            default_missing_value = "true"
+        )]
+        wrap_attributes: Option<bool>,
+    },
+
+    /// Explain an error code in detail.
+    Explain {
+        /// The error code to explain. E.g. `CE01`.
+        code: String,
+    },
+
+    /// Import a Rhai or yuck config as xml.
+    Import {
+        /// The file to import, or `-` for stdin.
+        file: String,
+
+        /// Format of the config. Defaults to yuck for `.yuck` files, and Rhai otherwise.
+        #[arg(long, value_enum)]
+        from: Option<ImportFormat>,
+    },
+
+    /// Create a new xmlman project.
+    Init {
+        /// Directory to create the project in.
+        dir: Option<String>,
+    },
+
+    /// Start a language server over stdio.
+    Lsp,
+
+    /// Print a schema of the supported xml elements.
+    Schema {
+        /// Format of the schema.
+        #[arg(long, value_enum, default_value_t = SchemaFormat::Xsd)]
+        format: SchemaFormat,
+    },
+
+    /// Watch files and directories, retranspiling them on change.
+    Watch {
+        /// Files and directories to watch. Defaults to the inputs in xmlman.toml.
+        paths: Vec<String>,
+    },
+}
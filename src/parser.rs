@@ -1,148 +1,391 @@
-//! The parser uses both _xmlparser_ and _xml-rs_ to parse xml.
-//! Both plays a crutial role in making _xmlman_ elegant.
-//! _xmlparser's_ role in parsing is validating the script,
-//! finding errors, generating spans etc.
-//! And _xml-rs's_ role is generating the AST (Abstract Syntax Tree).
+//! The parser uses _xmlparser_ to parse xml. The tokenizer only checks
+//! the syntax of each token, so the parser checks the rest of what makes
+//! xml well-formed itself: closing tags that match their start tag,
+//! attributes that are only set once, entity references and namespace
+//! prefixes. Every error has a span (start..end) into the script.
 //!
-//! Combining both of them gives xmlman the best of both worlds.
-//! Good errors throught spans (start..end) and fast AST parsing.
+//! The AST is stored in an arena, and the names and attribute values
+//! of its nodes are slices of the script wherever possible. So parsing
+//! barely allocates, and the AST can be sent to other threads.
 
-use super::FileInfo;
-use crate::error::{DiagInfo, print_diag_error};
-use std::cell::RefCell;
-use std::error::Error;
-use std::rc::Rc;
-use xml::reader::{EventReader, XmlEvent};
-use xmlparser::{StrSpan, Token, Tokenizer};
+use crate::FileInfo;
+use crate::error::DiagInfo;
+use std::borrow::Cow;
+use std::ops::Range;
+use xmlparser::{ElementEnd, StrSpan, Token, Tokenizer};
+
+/// [`NodeId`] is the index of a [`Node`] in its [`Ast`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NodeId(usize);
 
 /// [`Node`] is a structure created by _xmlparser_.
 /// It contains essential info such as the xml element
 /// name, attributes, children and span.
 #[derive(Debug)]
 pub struct Node<'a> {
-    pub name: String,
-    pub attributes: Vec<(String, String)>,
-    pub children: Vec<Rc<RefCell<Node<'a>>>>,
+    pub name: Cow<'a, str>,
+    pub attributes: Vec<(Cow<'a, str>, Cow<'a, str>)>,
+    pub children: Vec<NodeId>,
     pub span: Option<StrSpan<'a>>,
 }
 
-pub fn parse_xml<'a>(file_info: &FileInfo<'a>) -> Result<Rc<RefCell<Node<'a>>>, Box<dyn Error>> {
-    // First pass: xmlparser to gather spans and detect errors
-    let mut token_spans = Vec::new();
-    let tokenizer = Tokenizer::from(file_info.script);
-    for token_result in tokenizer {
-        match token_result {
-            Ok(token) => match token {
-                Token::ElementStart { span, .. }
-                | Token::ElementEnd { span, .. }
-                | Token::Attribute { span, .. }
-                | Token::ProcessingInstruction { span, .. } => {
-                    token_spans.push(span);
-                }
-                _ => {}
-            },
+/// [`Ast`] is the arena that holds every [`Node`] of a parsed script.
+#[derive(Debug)]
+pub struct Ast<'a> {
+    nodes: Vec<Node<'a>>,
+    root: NodeId,
+}
+
+impl<'a> Ast<'a> {
+    /// The root element of the script.
+    pub fn root(&self) -> &Node<'a> {
+        self.node(self.root)
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node<'a> {
+        &self.nodes[id.0]
+    }
+
+    /// The children of `node`, in the order they appear in the script.
+    pub fn children<'n>(&'n self, node: &'n Node<'a>) -> impl Iterator<Item = &'n Node<'a>> {
+        node.children.iter().map(|id| self.node(*id))
+    }
+}
+
+/// An element whose closing tag has not been read yet.
+struct OpenTag<'a> {
+    id: NodeId,
+    prefix: &'a str,
+    local: &'a str,
+    /// Length of the namespace prefixes in scope before this element.
+    prefixes: usize,
+}
+
+/// Parse the xml script in `file_info` into an [`Ast`].
+/// Returns a [`DiagInfo`] describing the first error that was found.
+pub fn parse_xml<'a>(file_info: &FileInfo<'a>) -> Result<Ast<'a>, DiagInfo<'a>> {
+    let script = file_info.script;
+    let mut nodes: Vec<Node<'a>> = Vec::new();
+    let mut root = None;
+    let mut open_tags: Vec<OpenTag<'a>> = Vec::new();
+    // The element whose start tag is being read
+    let mut open: Option<OpenTag<'a>> = None;
+    // Names of its attributes, and the prefixes that they use
+    let mut attr_names: Vec<(&'a str, &'a str)> = Vec::new();
+    let mut attr_prefixes: Vec<StrSpan<'a>> = Vec::new();
+    // Namespace prefixes that are declared by the open elements
+    let mut prefixes: Vec<&'a str> = Vec::new();
+
+    for token_result in Tokenizer::from(script) {
+        let token = match token_result {
+            Ok(token) => token,
             Err(e) => {
                 // since its a parse error, we dont have span
                 // we need to make up a span
                 let pos = e.pos();
+                let start_byte = byte_offset(script, pos.row as usize, pos.col as usize);
 
-                let mut start_byte = 0;
-                let mut current_row = 1;
-
-                for line in file_info.script.lines() {
-                    if current_row == pos.row {
-                        start_byte += line
-                            .char_indices()
-                            .nth((pos.col - 1) as usize)
-                            .map(|(byte_idx, _)| byte_idx)
-                            .unwrap_or(line.len());
-                        break;
-                    } else {
-                        start_byte += line.len() + 1;
-                        current_row += 1;
-                    }
+                return Err(parse_error(e.to_string(), start_byte..start_byte + 1));
+            }
+        };
+
+        match token {
+            Token::ElementStart { prefix, local, span } => {
+                let id = NodeId(nodes.len());
+                nodes.push(Node {
+                    name: Cow::Borrowed(local.as_str()),
+                    attributes: vec![],
+                    children: vec![],
+                    span: Some(span),
+                });
+
+                match open_tags.last() {
+                    Some(parent) => nodes[parent.id.0].children.push(id),
+                    None => root = root.or(Some(id)),
                 }
 
-                print_diag_error(
-                    Some(file_info.file_path),
-                    file_info.script,
-                    DiagInfo {
-                        message: e.to_string(),
-                        label: Some("here"),
-                        note: None,
-                        span: Some(start_byte..start_byte + 1),
-                    },
-                );
-
-                return Err(Box::new(e));
+                open = Some(OpenTag {
+                    id,
+                    prefix: prefix.as_str(),
+                    local: local.as_str(),
+                    prefixes: prefixes.len(),
+                });
+                attr_names.clear();
+                attr_prefixes.clear();
             }
-        }
-    }
+            Token::Attribute { prefix, local, value, span } => {
+                let name = (prefix.as_str(), local.as_str());
+                if attr_names.contains(&name) {
+                    let message = format!("Attribute '{}' is set more than once", qname(name));
+                    return Err(parse_error(message, span.range()));
+                }
+                attr_names.push(name);
 
-    // Second pass: xml-rs to build AST
-    let parser = EventReader::from_str(file_info.script);
-    let mut root_node: Option<Rc<RefCell<Node<'a>>>> = None;
-    let mut node_stack: Vec<Rc<RefCell<Node<'a>>>> = Vec::new();
-    let mut token_iter = Tokenizer::from(file_info.script).filter_map(|r| r.ok());
-
-    for e in parser {
-        match e {
-            Ok(XmlEvent::StartElement { name, attributes, .. }) => {
-                let span = loop {
-                    match token_iter.next() {
-                        Some(Token::ElementStart { span, .. }) => break Some(span),
-                        Some(_) => continue,
-                        None => break None,
+                // Namespace declarations are not attributes of the element
+                if prefix.as_str() == "xmlns" {
+                    prefixes.push(local.as_str());
+                    continue;
+                }
+                if prefix.is_empty() && local.as_str() == "xmlns" {
+                    continue;
+                }
+
+                if !prefix.is_empty() {
+                    attr_prefixes.push(prefix);
+                }
+                check_references(value)?;
+
+                if let Some(tag) = &open {
+                    nodes[tag.id.0]
+                        .attributes
+                        .push((Cow::Borrowed(local.as_str()), unescape(value.as_str())));
+                }
+            }
+            Token::ElementEnd { end, span } => match end {
+                ElementEnd::Open | ElementEnd::Empty => {
+                    let Some(tag) = open.take() else { continue };
+
+                    // Prefixes can be declared after they are used in the start tag
+                    if !is_bound(&prefixes, tag.prefix) {
+                        let message = format!(
+                            "The prefix of element '{}' is not declared",
+                            qname((tag.prefix, tag.local))
+                        );
+                        return Err(parse_error(message, nodes[tag.id.0].span.unwrap().range()));
+                    }
+                    if let Some(prefix) = attr_prefixes.iter().find(|p| !is_bound(&prefixes, p)) {
+                        let message = format!("The prefix '{}' is not declared", prefix);
+                        return Err(parse_error(message, prefix.range()));
                     }
-                };
-
-                let node = Rc::new(RefCell::new(Node {
-                    name: name.local_name,
-                    attributes: attributes
-                        .into_iter()
-                        .map(|attr| (attr.name.local_name, attr.value))
-                        .collect(),
-                    children: vec![],
-                    span,
-                }));
 
-                if let Some(parent) = node_stack.last() {
-                    parent.borrow_mut().children.push(node.clone());
-                } else {
-                    root_node = Some(node.clone());
+                    match end {
+                        ElementEnd::Open => open_tags.push(tag),
+                        _ => prefixes.truncate(tag.prefixes),
+                    }
                 }
+                ElementEnd::Close(prefix, local) => match open_tags.pop() {
+                    Some(tag) if tag.prefix == prefix.as_str() && tag.local == local.as_str() => {
+                        prefixes.truncate(tag.prefixes);
+                    }
+                    Some(tag) => {
+                        let message = format!(
+                            "Closing tag '{}' does not match the start tag '{}'",
+                            qname((prefix.as_str(), local.as_str())),
+                            qname((tag.prefix, tag.local))
+                        );
+                        return Err(parse_error(message, span.range()));
+                    }
+                    None => {
+                        let message = "Closing tag without a start tag".to_string();
+                        return Err(parse_error(message, span.range()));
+                    }
+                },
+            },
+            Token::Text { text } => check_references(text)?,
+            _ => {}
+        }
+    }
+
+    if let Some(tag) = open_tags.last() {
+        let message = format!(
+            "The script ends before element '{}' is closed",
+            qname((tag.prefix, tag.local))
+        );
+        return Err(parse_error(message, nodes[tag.id.0].span.unwrap().range()));
+    }
+
+    match root {
+        Some(root) => Ok(Ast { nodes, root }),
+        None => Err(DiagInfo {
+            code: "PE02",
+            message: "XML should have a root node".to_string(),
+            label: None,
+            note: None,
+            span: None,
+        }),
+    }
+}
 
-                node_stack.push(node);
+fn parse_error<'a>(message: String, span: Range<usize>) -> DiagInfo<'a> {
+    DiagInfo { code: "PE01", message, label: Some("here"), note: None, span: Some(span) }
+}
+
+/// A name with its prefix, as it is written in the script.
+fn qname((prefix, local): (&str, &str)) -> String {
+    match prefix {
+        "" => local.to_string(),
+        prefix => format!("{}:{}", prefix, local),
+    }
+}
+
+/// Whether `prefix` can be used, as it is empty, predefined or declared.
+fn is_bound(prefixes: &[&str], prefix: &str) -> bool {
+    matches!(prefix, "" | "xml" | "xmlns") || prefixes.contains(&prefix)
+}
+
+/// Check that every entity and character reference in `text` can be replaced.
+fn check_references<'a>(text: StrSpan<'_>) -> Result<(), DiagInfo<'a>> {
+    let mut offset = 0;
+
+    while let Some(start) = text.as_str()[offset..].find('&') {
+        let at = offset + start;
+        let rest = &text.as_str()[at + 1..];
+        let span = text.start() + at..text.start() + at + 1;
+
+        let Some(end) = rest.find(';').filter(|&end| {
+            end > 0 && !rest[..end].contains(|c: char| c.is_whitespace() || c == '&')
+        }) else {
+            let message = "A '&' that does not start a reference should be written as '&amp;'";
+            return Err(parse_error(message.to_string(), span));
+        };
+
+        let name = &rest[..end];
+        if reference(name).is_none() {
+            let message = format!("Unknown entity or character reference: '&{};'", name);
+            return Err(parse_error(message, span.start..span.start + end + 2));
+        }
+
+        offset = at + end + 2;
+    }
+
+    Ok(())
+}
+
+/// The character that the reference `&name;` stands for.
+fn reference(name: &str) -> Option<char> {
+    match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        _ => name
+            .strip_prefix("#x")
+            .map(|hex| u32::from_str_radix(hex, 16))
+            .or_else(|| name.strip_prefix('#').map(str::parse))
+            .and_then(Result::ok)
+            .and_then(char::from_u32)
+            .filter(|&c| {
+                matches!(c, '\t' | '\n' | '\r') || c >= ' ' && c != '\u{FFFE}' && c != '\u{FFFF}'
+            }),
+    }
+}
+
+/// Normalize an attribute value like xml parsers do: replace the entity and
+/// character references, and turn every line break and tab into a space.
+/// Values that don't need either are borrowed as they are.
+fn unescape(value: &str) -> Cow<'_, str> {
+    if !value.contains(['&', '\t', '\n', '\r']) {
+        return Cow::Borrowed(value);
+    }
+
+    let mut unescaped = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(start) = rest.find(['&', '\t', '\n', '\r']) {
+        unescaped.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if !rest.starts_with('&') {
+            // A \r\n line break is a single space
+            let len = if rest.starts_with("\r\n") { 2 } else { 1 };
+            unescaped.push(' ');
+            rest = &rest[len..];
+            continue;
+        }
+
+        let Some(end) = rest.find(';') else { break };
+        let c = reference(&rest[1..end]);
+
+        match c {
+            Some(c) => {
+                unescaped.push(c);
+                rest = &rest[end + 1..];
             }
-            Ok(XmlEvent::EndElement { .. }) => {
-                node_stack.pop();
+            // Unknown references are caught by check_references
+            None => {
+                unescaped.push('&');
+                rest = &rest[1..];
             }
-            Ok(XmlEvent::Characters(_text)) => {}
-            Ok(_) => {}
+        }
+    }
 
-            Err(e) => {
-                // parser-level errors
-                print_diag_error(
-                    Some(file_info.file_path),
-                    file_info.script,
-                    DiagInfo { message: e.to_string(), label: None, note: None, span: None },
-                );
-                return Err(Box::new(e));
-            }
+    unescaped.push_str(rest);
+    Cow::Owned(unescaped)
+}
+
+/// Byte offset of the 1-based `row` and `col` in `script`.
+fn byte_offset(script: &str, row: usize, col: usize) -> usize {
+    let mut start_byte = 0;
+    let mut current_row = 1;
+
+    for line in script.lines() {
+        if current_row == row {
+            start_byte += line
+                .char_indices()
+                .nth(col.saturating_sub(1))
+                .map(|(byte_idx, _)| byte_idx)
+                .unwrap_or(line.len());
+            break;
+        } else {
+            start_byte += line.len() + 1;
+            current_row += 1;
         }
     }
 
-    Ok(root_node.ok_or_else(|| {
-        print_diag_error(
-            Some(file_info.file_path),
-            file_info.script,
-            DiagInfo {
-                message: "XML should have a root node".to_string(),
-                label: None,
-                note: None,
-                span: None,
-            },
+    start_byte
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(script: &str) -> Result<Ast<'_>, DiagInfo<'_>> {
+        parse_xml(&FileInfo { file_path: "test.xml", script })
+    }
+
+    #[test]
+    fn malformed_xml_is_rejected() {
+        let scripts = [
+            "<Root><Box></Label></Root>",
+            "<Root><Box>",
+            "<Root></Root></Box>",
+            "<Root a=\"1\" a=\"2\"/>",
+            "<Root a=\"&foo;\"/>",
+            "<Root>&foo;</Root>",
+            "<Root a=\"&#0;\"/>",
+            "<Root a=\"a & b\"/>",
+            "<Root/>hello",
+            "<Root><x:Box/></Root>",
+            "<Root><Box x:a=\"1\"/></Root>",
+            "<a:Root xmlns:a=\"x\"></b:Root>",
+        ];
+
+        for script in scripts {
+            let err = parse(script).err().unwrap_or_else(|| panic!("{} parsed", script));
+            assert_eq!(err.code, "PE01", "{}", script);
+
+            let span = err.span.unwrap();
+            assert!(span.start <= script.len(), "{}: {:?}", script, span);
+        }
+    }
+
+    #[test]
+    fn well_formed_xml_is_parsed() {
+        let script = concat!(
+            "<?xml version=\"1.0\"?>\n",
+            "<!-- comment -->\n",
+            "<Root xmlns=\"urn:x\" xmlns:a=\"urn:a\">\n",
+            "  <a:Box a:x=\"&lt;&#65;&#x42;&quot;\">&amp; text<![CDATA[ & ]]></a:Box>\n",
+            "  <Label xml:lang=\"en\" text=\"a\nb\"/>\n",
+            "</Root>\n",
         );
-        "XML should have a root node"
-    })?)
+
+        let ast = parse(script).unwrap();
+        let children: Vec<_> = ast.children(ast.root()).collect();
+
+        assert_eq!(children[0].name, "Box");
+        assert_eq!(children[0].attributes[0].1, "<AB\"");
+        assert_eq!(children[1].attributes[1].1, "a b");
+    }
 }
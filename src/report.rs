@@ -0,0 +1,144 @@
+//! Buffered output of the files that are processed.
+//!
+//! Files are processed in parallel, so everything that is printed while
+//! processing a file is collected in a [`Report`] first. The reports are
+//! then printed in the order of the files, which keeps the output the
+//! same no matter how many threads are used.
+
+use crate::OutputOptions;
+use crate::opts::MessageFormat;
+
+use log::{error, info, warn};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use xmlman::InputFile;
+use xmlman::error::{DiagInfo, diag_json, error_json, write_diag_error};
+
+enum Line {
+    Info(String),
+    Warn(String),
+    Error(String),
+    Stdout(String),
+    Stderr(String),
+}
+
+/// [`Report`] is everything that processing a single file prints.
+#[derive(Default)]
+pub(crate) struct Report {
+    lines: Vec<Line>,
+}
+
+impl Report {
+    pub fn info(&mut self, message: String) {
+        self.lines.push(Line::Info(message));
+    }
+
+    pub fn warn(&mut self, message: String) {
+        self.lines.push(Line::Warn(message));
+    }
+
+    pub fn error(&mut self, message: String) {
+        self.lines.push(Line::Error(message));
+    }
+
+    pub fn stdout(&mut self, text: String) {
+        self.lines.push(Line::Stdout(text));
+    }
+
+    /// Add a diagnostic in the chosen message format.
+    pub fn diag(&mut self, output: &OutputOptions, file: &str, source_code: &str, info: DiagInfo) {
+        let text = match output.message_format {
+            MessageFormat::Human => {
+                let mut buffer = Vec::new();
+                write_diag_error(Some(file), source_code, info, &mut buffer)
+                    .expect("Failed to render diagnostic");
+                String::from_utf8_lossy(&buffer).into_owned()
+            }
+            MessageFormat::Json => format!("{}\n", diag_json(Some(file), source_code, info)),
+        };
+
+        self.diag_text(output, text);
+    }
+
+    /// Add an error that does not point into a file, like a file that could
+    /// not be read. It is a JSON diagnostic without a code in JSON mode.
+    pub fn fail(&mut self, output: &OutputOptions, file: Option<&str>, message: String) {
+        match output.message_format {
+            MessageFormat::Human => self.error(message),
+            MessageFormat::Json => {
+                self.diag_text(output, format!("{}\n", error_json(file, &message)))
+            }
+        }
+    }
+
+    fn diag_text(&mut self, output: &OutputOptions, text: String) {
+        // JSON diagnostics are read from stdout, unless it has the output
+        match output.message_format {
+            MessageFormat::Json if !output.to_stdout => self.stdout(text),
+            _ => self.lines.push(Line::Stderr(text)),
+        }
+    }
+
+    pub fn print(self) {
+        for line in self.lines {
+            match line {
+                Line::Info(message) => info!("{}", message),
+                Line::Warn(message) => warn!("{}", message),
+                Line::Error(message) => error!("{}", message),
+                Line::Stdout(text) => print!("{}", text),
+                Line::Stderr(text) => eprint!("{}", text),
+            }
+        }
+    }
+}
+
+/// Run `job` on every file with up to `jobs` threads, printing the
+/// report of each file as soon as the files before it are done.
+/// Returns the number of files that `job` failed on.
+pub(crate) fn run_jobs<F>(files: &[InputFile], jobs: usize, job: F) -> usize
+where
+    F: Fn(&InputFile, &mut Report) -> bool + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let (tx, rx) = mpsc::channel();
+    let mut failed = 0;
+
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, files.len().max(1)) {
+            let (tx, next, job) = (tx.clone(), &next, &job);
+
+            scope.spawn(move || {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(file) = files.get(index) else { break };
+
+                    let mut report = Report::default();
+                    let ok = job(file, &mut report);
+
+                    if tx.send((index, ok, report)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(tx);
+
+        // Reports of files that are done before the ones in front of them
+        let mut done = BTreeMap::new();
+        let mut next_to_print = 0;
+
+        for (index, ok, report) in rx {
+            done.insert(index, (ok, report));
+
+            while let Some((ok, report)) = done.remove(&next_to_print) {
+                report.print();
+                failed += usize::from(!ok);
+                next_to_print += 1;
+            }
+        }
+    });
+
+    failed
+}
@@ -0,0 +1,144 @@
+//! Schemas of the xml elements that xmlman supports, for `xmlman schema`.
+//!
+//! The schema is generated from the [widget registry](crate::widgets), so
+//! generic xml tooling can complete and validate configs without xmlman.
+//! Attributes are passed through to ewwii as they are, so the schema
+//! lists the known ones but allows any other attribute as well.
+
+use crate::widgets::{WIDGETS, WidgetInfo, WidgetKind};
+use std::collections::BTreeMap;
+
+/// Generate an XSD schema. Custom widgets, from the element name to
+/// the rhai function, are allowed anywhere a widget is.
+pub fn to_xsd(custom_widgets: &BTreeMap<String, String>) -> String {
+    let mut out = String::new();
+
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str("<xs:schema xmlns:xs=\"http://www.w3.org/2001/XMLSchema\">\n");
+
+    // Elements that can be used as a widget
+    out.push_str("\n    <xs:group name=\"widget\">\n        <xs:choice>\n");
+    let widget_names = WIDGETS
+        .iter()
+        .filter(|w| matches!(w.kind, WidgetKind::Container | WidgetKind::Leaf))
+        .map(|w| w.name)
+        .chain(custom_widgets.keys().map(String::as_str));
+    for name in widget_names {
+        out.push_str(&format!("            <xs:element ref=\"{}\"/>\n", name));
+    }
+    out.push_str("        </xs:choice>\n    </xs:group>\n");
+
+    for widget in WIDGETS {
+        out.push('\n');
+        write_element(widget, &mut out);
+    }
+
+    for (name, function) in custom_widgets {
+        out.push('\n');
+        write_custom_element(name, function, &mut out);
+    }
+
+    out.push_str("</xs:schema>\n");
+    out
+}
+
+fn write_element(widget: &WidgetInfo, out: &mut String) {
+    out.push_str(&format!("    <xs:element name=\"{}\">\n", widget.name));
+    write_documentation(widget.description, widget.function, out);
+    out.push_str("        <xs:complexType>\n");
+
+    match widget.kind {
+        WidgetKind::Root => {
+            out.push_str("            <xs:choice minOccurs=\"0\" maxOccurs=\"unbounded\">\n");
+            for top_level in WIDGETS.iter().filter(|w| w.kind == WidgetKind::TopLevel) {
+                out.push_str(&format!(
+                    "                <xs:element ref=\"{}\"/>\n",
+                    top_level.name
+                ));
+            }
+            out.push_str("            </xs:choice>\n");
+        }
+        // A window must contain exactly one widget
+        WidgetKind::TopLevel if widget.name == "Window" => {
+            out.push_str("            <xs:group ref=\"widget\"/>\n");
+        }
+        WidgetKind::Container => {
+            out.push_str(
+                "            <xs:group ref=\"widget\" minOccurs=\"0\" maxOccurs=\"unbounded\"/>\n",
+            );
+        }
+        WidgetKind::TopLevel | WidgetKind::Leaf => {}
+    }
+
+    for attribute in widget.all_attributes() {
+        // A window without a name can not be transpiled
+        let required = widget.name == "Window" && attribute == "name";
+        let use_ = if required { " use=\"required\"" } else { "" };
+
+        out.push_str(&format!(
+            "            <xs:attribute name=\"{}\" type=\"xs:string\"{}/>\n",
+            attribute, use_
+        ));
+    }
+
+    if widget.kind != WidgetKind::Root {
+        out.push_str("            <xs:anyAttribute processContents=\"skip\"/>\n");
+    }
+
+    out.push_str("        </xs:complexType>\n    </xs:element>\n");
+}
+
+/// Custom widgets take any attribute, and any number of children.
+fn write_custom_element(name: &str, function: &str, out: &mut String) {
+    out.push_str(&format!("    <xs:element name=\"{}\">\n", name));
+    write_documentation("Custom widget from xmlman.toml.", function, out);
+    out.push_str("        <xs:complexType>\n");
+    out.push_str(
+        "            <xs:group ref=\"widget\" minOccurs=\"0\" maxOccurs=\"unbounded\"/>\n",
+    );
+    out.push_str("            <xs:anyAttribute processContents=\"skip\"/>\n");
+    out.push_str("        </xs:complexType>\n    </xs:element>\n");
+}
+
+fn write_documentation(description: &str, function: &str, out: &mut String) {
+    out.push_str(&format!(
+        "        <xs:annotation>\n            <xs:documentation>{} Transpiled to `{}`.</xs:documentation>\n        </xs:annotation>\n",
+        escape(description),
+        escape(function)
+    ));
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schema_covers_every_widget() {
+        let xsd = to_xsd(&BTreeMap::new());
+
+        for widget in WIDGETS {
+            let start = format!("    <xs:element name=\"{}\">\n", widget.name);
+            let at = xsd.find(&start).unwrap_or_else(|| panic!("no element for {}", widget.name));
+            let end = at + xsd[at..].find("    </xs:element>\n").unwrap();
+            let element = &xsd[at..end];
+
+            for attribute in widget.all_attributes() {
+                let declaration = format!("<xs:attribute name=\"{}\"", attribute);
+                assert!(element.contains(&declaration), "{}.{}", widget.name, attribute);
+            }
+        }
+    }
+
+    #[test]
+    fn custom_widgets_are_widgets() {
+        let custom = [("Clock".to_string(), "clock".to_string())].into();
+        let xsd = to_xsd(&custom);
+
+        assert!(xsd.contains("<xs:element name=\"Clock\">"));
+        assert!(xsd.contains("<xs:element ref=\"Clock\"/>"));
+    }
+}
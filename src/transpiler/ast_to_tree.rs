@@ -1,26 +1,38 @@
 use super::tree::{Attr, InternalTree, Span};
-use crate::FileInfo;
+use crate::TranspileOptions;
 use crate::error::DiagInfo;
-use crate::parser::Node;
-use std::cell::RefCell;
-use std::rc::Rc;
-
-pub fn node_to_internal_tree<'a>(
-    node_ref: Rc<RefCell<Node<'a>>>,
-    file_info: &'a FileInfo,
-) -> Result<InternalTree, DiagInfo<'a>> {
-    let node = node_ref.borrow();
+use crate::parser::{Ast, Node};
+use crate::widgets::widget;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 
+/// Convert `node` and its children. The tree borrows the names and
+/// values of the [`Ast`], so only expanded defines are allocated.
+pub fn node_to_internal_tree<'t>(
+    ast: &'t Ast<'_>,
+    node: &'t Node<'_>,
+    options: &'t TranspileOptions,
+) -> Result<InternalTree<'t>, DiagInfo<'static>> {
     // Convert attributes
-    let mut attrs: Vec<Attr> =
-        node.attributes.iter().map(|(k, v)| Attr { key: k.clone(), value: v.clone() }).collect();
+    let mut attrs: Vec<Attr> = node
+        .attributes
+        .iter()
+        .map(|(k, v)| match expand_defines(v, &options.defines) {
+            Ok(value) => Ok(Attr { key: Cow::Borrowed(k), value }),
+            Err(name) => Err(DiagInfo {
+                code: "TE06",
+                message: format!("Unknown define: '{}'", name),
+                label: Some("used in this element"),
+                note: Some("Defines are set in the [defines] table of xmlman.toml."),
+                span: node.span.as_ref().map(|s| s.range()),
+            }),
+        })
+        .collect::<Result<_, _>>()?;
 
     // Recursively convert children
-    let children: Vec<InternalTree> = node
-        .children
-        .iter()
-        .cloned()
-        .map(|child| node_to_internal_tree(child, file_info))
+    let children: Vec<InternalTree> = ast
+        .children(node)
+        .map(|child| node_to_internal_tree(ast, child, options))
         .collect::<Result<_, _>>()?;
 
     // Convert spans
@@ -29,36 +41,14 @@ pub fn node_to_internal_tree<'a>(
         end: node.span.as_ref().map(|s| s.end()),
     };
 
-    match node.name.as_str() {
-        "Label" => Ok(InternalTree::Label { attrs, span }),
-        "Box" => Ok(InternalTree::Box { attrs, children, span }),
-        "CenterBox" => Ok(InternalTree::CenterBox { attrs, children, span }),
-        "Button" => Ok(InternalTree::Button { attrs, span }),
-        "Image" => Ok(InternalTree::Image { attrs, span }),
-        "Input" => Ok(InternalTree::Input { attrs, span }),
-        "Progress" => Ok(InternalTree::Progress { attrs, span }),
-        "ComboBoxText" => Ok(InternalTree::ComboBoxText { attrs, span }),
-        "Slider" => Ok(InternalTree::Slider { attrs, span }),
-        "Checkbox" => Ok(InternalTree::Checkbox { attrs, span }),
-        "Expander" => Ok(InternalTree::Expander { attrs, children, span }),
-        "Revealer" => Ok(InternalTree::Revealer { attrs, children, span }),
-        "Scroll" => Ok(InternalTree::Scroll { attrs, children, span }),
-        "OverLay" => Ok(InternalTree::OverLay { attrs, children, span }),
-        "Stack" => Ok(InternalTree::Stack { attrs, children, span }),
-        "Calendar" => Ok(InternalTree::Calendar { attrs, span }),
-        "ColorButton" => Ok(InternalTree::ColorButton { attrs, span }),
-        "ColorChooser" => Ok(InternalTree::ColorChooser { attrs, span }),
-        "CircularProgress" => Ok(InternalTree::CircularProgress { attrs, span }),
-        "Graph" => Ok(InternalTree::Graph { attrs, span }),
-        "Transform" => Ok(InternalTree::Transform { attrs, span }),
-        "EventBox" => Ok(InternalTree::EventBox { attrs, children, span }),
-        "ToolTip" => Ok(InternalTree::ToolTip { attrs, children, span }),
+    match node.name.as_ref() {
         "Window" => {
             let name_attr = match attrs.iter().find(|a| a.key == "name").map(|a| a.value.clone()) {
                 Some(a) => a,
                 None => {
                     return Err(DiagInfo {
-                        message: format!("A window without a name was found!"),
+                        code: "TE02",
+                        message: "A window without a name was found!".to_string(),
                         label: Some("Add a name attribute in this element."),
                         note: None,
                         span: node.span.as_ref().map(|s| s.range()),
@@ -72,7 +62,8 @@ pub fn node_to_internal_tree<'a>(
                 Box::new(children.into_iter().next().unwrap())
             } else {
                 return Err(DiagInfo {
-                    message: format!("Found a window with multiple children."),
+                    code: "TE03",
+                    message: "Found a window with multiple children.".to_string(),
                     label: Some("here"),
                     note: Some("A window much contain exactly 1 child."),
                     span: node.span.as_ref().map(|s| s.range()),
@@ -94,13 +85,86 @@ pub fn node_to_internal_tree<'a>(
             Ok(InternalTree::Listen { var: var_name, attrs, span })
         }
         "Root" => Ok(InternalTree::Enter { children, span }),
-        unknown => {
-            return Err(DiagInfo {
-                message: format!("Unknown XML element: '{}'", unknown),
-                label: Some("here"),
-                note: None,
-                span: node.span.as_ref().map(|s| s.range()),
-            });
+        // Built-in widgets can't be replaced by custom widgets
+        name if widget(name).is_none() && options.widgets.contains_key(name) => {
+            let function = Cow::Borrowed(options.widgets[name].as_str());
+            Ok(InternalTree::Custom { function, attrs, children, span })
         }
+        name => widget_node(name, attrs, children, span).ok_or_else(|| DiagInfo {
+            code: "TE01",
+            message: format!("Unknown XML element: '{}'", name),
+            label: Some("here"),
+            note: Some("Custom widgets can be added in the [widgets] table of xmlman.toml."),
+            span: node.span.as_ref().map(|s| s.range()),
+        }),
+    }
+}
+
+/// Create the built-in widget `name`. Leaf widgets drop the children.
+/// Returns `None` if `name` is not a built-in widget.
+pub fn widget_node<'a>(
+    name: &str,
+    attrs: Vec<Attr<'a>>,
+    children: Vec<InternalTree<'a>>,
+    span: Span,
+) -> Option<InternalTree<'a>> {
+    let tree = match name {
+        "Label" => InternalTree::Label { attrs, span },
+        "Box" => InternalTree::Box { attrs, children, span },
+        "CenterBox" => InternalTree::CenterBox { attrs, children, span },
+        "Button" => InternalTree::Button { attrs, span },
+        "Image" => InternalTree::Image { attrs, span },
+        "Input" => InternalTree::Input { attrs, span },
+        "Progress" => InternalTree::Progress { attrs, span },
+        "ComboBoxText" => InternalTree::ComboBoxText { attrs, span },
+        "Slider" => InternalTree::Slider { attrs, span },
+        "Checkbox" => InternalTree::Checkbox { attrs, span },
+        "Expander" => InternalTree::Expander { attrs, children, span },
+        "Revealer" => InternalTree::Revealer { attrs, children, span },
+        "Scroll" => InternalTree::Scroll { attrs, children, span },
+        "OverLay" => InternalTree::OverLay { attrs, children, span },
+        "Stack" => InternalTree::Stack { attrs, children, span },
+        "Calendar" => InternalTree::Calendar { attrs, span },
+        "ColorButton" => InternalTree::ColorButton { attrs, span },
+        "ColorChooser" => InternalTree::ColorChooser { attrs, span },
+        "CircularProgress" => InternalTree::CircularProgress { attrs, span },
+        "Graph" => InternalTree::Graph { attrs, span },
+        "Transform" => InternalTree::Transform { attrs, span },
+        "EventBox" => InternalTree::EventBox { attrs, children, span },
+        "ToolTip" => InternalTree::ToolTip { attrs, children, span },
+        _ => return None,
+    };
+
+    Some(tree)
+}
+
+/// Replace every `@define(NAME)` in `value` with the value of `NAME`.
+/// Returns the name of the first define that does not exist as the error.
+fn expand_defines<'v>(
+    value: &'v str,
+    defines: &BTreeMap<String, String>,
+) -> Result<Cow<'v, str>, String> {
+    const DEFINE: &str = "@define(";
+
+    if !value.contains(DEFINE) {
+        return Ok(Cow::Borrowed(value));
+    }
+
+    let mut expanded = String::new();
+    let mut rest = value;
+
+    while let Some(start) = rest.find(DEFINE) {
+        let after = &rest[start + DEFINE.len()..];
+        let Some(end) = after.find(')') else { break };
+
+        let name = after[..end].trim();
+        let define = defines.get(name).ok_or_else(|| name.to_string())?;
+
+        expanded.push_str(&rest[..start]);
+        expanded.push_str(define);
+        rest = &after[end + 1..];
     }
+
+    expanded.push_str(rest);
+    Ok(Cow::Owned(expanded))
 }
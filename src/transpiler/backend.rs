@@ -0,0 +1,122 @@
+use super::InternalTree;
+use super::print_rhai::print_rhai;
+use super::tree_to_rhai::{internal_tree_to_rhai, internal_tree_to_rhai_modules};
+use super::tree_to_yuck::internal_tree_to_yuck;
+use super::verify::verify_rhai;
+use crate::TranspileOptions;
+use crate::error::DiagInfo;
+use serde::Deserialize;
+use std::path::PathBuf;
+
+/// [`Generated`] is the config that is generated from a single input.
+#[derive(Debug, Clone, Default)]
+pub struct Generated {
+    /// The config that is written to the output path of the input.
+    pub code: String,
+    /// Files that the config imports, by their path relative to it.
+    pub modules: Vec<(PathBuf, String)>,
+}
+
+/// [`Backend`] generates the config of a widget system from the [`InternalTree`].
+pub trait Backend {
+    /// Extension of the generated files, without the dot.
+    fn extension(&self) -> &'static str;
+
+    /// Generate the config from the tree.
+    fn generate(
+        &self,
+        tree: &InternalTree,
+        options: &TranspileOptions,
+    ) -> Result<String, DiagInfo<'static>>;
+
+    /// Generate an entry config that imports a module for each window.
+    /// Backends that can't import other files generate a single config.
+    fn generate_split(
+        &self,
+        tree: &InternalTree,
+        options: &TranspileOptions,
+    ) -> Result<Generated, DiagInfo<'static>> {
+        Ok(Generated { code: self.generate(tree, options)?, modules: Vec::new() })
+    }
+
+    /// Make sure that the generated config is valid.
+    /// The span of the error points into the generated config.
+    fn verify(&self, _code: &str) -> Result<(), DiagInfo<'static>> {
+        Ok(())
+    }
+}
+
+/// Rhai scripts for ewwii.
+pub struct RhaiBackend;
+
+impl Backend for RhaiBackend {
+    fn extension(&self) -> &'static str {
+        "rhai"
+    }
+
+    fn generate(
+        &self,
+        tree: &InternalTree,
+        options: &TranspileOptions,
+    ) -> Result<String, DiagInfo<'static>> {
+        internal_tree_to_rhai(tree, &options.rhai_style)
+    }
+
+    fn generate_split(
+        &self,
+        tree: &InternalTree,
+        options: &TranspileOptions,
+    ) -> Result<Generated, DiagInfo<'static>> {
+        let (entry, modules) = internal_tree_to_rhai_modules(tree)?;
+        let style = &options.rhai_style;
+
+        Ok(Generated {
+            code: print_rhai(&entry, style),
+            modules: modules
+                .iter()
+                .map(|(path, module)| {
+                    (format!("{}.{}", path, self.extension()).into(), print_rhai(module, style))
+                })
+                .collect(),
+        })
+    }
+
+    fn verify(&self, code: &str) -> Result<(), DiagInfo<'static>> {
+        verify_rhai(code)
+    }
+}
+
+/// Yuck configs for eww. There is no yuck parser to verify them with.
+pub struct YuckBackend;
+
+impl Backend for YuckBackend {
+    fn extension(&self) -> &'static str {
+        "yuck"
+    }
+
+    fn generate(
+        &self,
+        tree: &InternalTree,
+        _options: &TranspileOptions,
+    ) -> Result<String, DiagInfo<'static>> {
+        internal_tree_to_yuck(tree)
+    }
+}
+
+/// [`Target`] is the backend that files are transpiled with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Target {
+    #[default]
+    Rhai,
+    Yuck,
+}
+
+impl Target {
+    pub fn backend(self) -> &'static dyn Backend {
+        match self {
+            Target::Rhai => &RhaiBackend,
+            Target::Yuck => &YuckBackend,
+        }
+    }
+}
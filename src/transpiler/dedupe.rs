@@ -0,0 +1,252 @@
+use super::InternalTree;
+use super::tree_to_rhai::widget;
+use std::collections::HashMap;
+
+/// Repeated subtrees with fewer widgets than this are left where they are.
+const MIN_SHARED_SIZE: usize = 3;
+
+/// [`SharedSubtrees`] are widget subtrees that are repeated in the tree,
+/// and are generated once as a `shared_N` function instead.
+pub struct SharedSubtrees<'a> {
+    /// Each shared subtree, by its address in the tree, to the id of its
+    /// structure. Subtrees with the same structure have the same id.
+    ids: HashMap<*const InternalTree<'a>, usize>,
+}
+
+/// A widget, with its children replaced by their ids.
+#[derive(PartialEq, Eq, Hash)]
+struct Node<'t> {
+    function: &'t str,
+    attrs: Vec<(&'t str, &'t str)>,
+    children: Option<Vec<usize>>,
+}
+
+#[derive(Default)]
+struct Interner<'t, 'a> {
+    nodes: HashMap<Node<'t>, usize>,
+    /// Number of widgets in each structure, by id.
+    sizes: Vec<usize>,
+    /// Ids of every widget in the tree, by address.
+    ids: HashMap<*const InternalTree<'a>, usize>,
+}
+
+impl<'t, 'a> Interner<'t, 'a> {
+    /// Give every widget in `tree` the id of its structure, and return the id of `tree`.
+    fn intern(&mut self, tree: &'t InternalTree<'a>) -> Option<usize> {
+        let Some((function, attrs, children, _)) = widget(tree) else {
+            match tree {
+                InternalTree::Enter { children, .. } => children.iter().for_each(|c| {
+                    self.intern(c);
+                }),
+                InternalTree::DefWindow { node, .. } => {
+                    self.intern(node);
+                }
+                _ => {}
+            }
+            return None;
+        };
+
+        let children: Option<Vec<usize>> =
+            children.map(|c| c.iter().filter_map(|child| self.intern(child)).collect());
+        let size = 1 + children.iter().flatten().map(|&id| self.sizes[id]).sum::<usize>();
+
+        let node = Node {
+            function,
+            attrs: attrs.iter().map(|a| (a.key.as_ref(), a.value.as_ref())).collect(),
+            children,
+        };
+
+        let next = self.sizes.len();
+        let id = *self.nodes.entry(node).or_insert(next);
+        if id == next {
+            self.sizes.push(size);
+        }
+
+        self.ids.insert(tree, id);
+        Some(id)
+    }
+}
+
+impl<'a> SharedSubtrees<'a> {
+    pub fn find(tree: &InternalTree<'a>) -> Self {
+        let mut interner = Interner::default();
+        interner.intern(tree);
+
+        // Count the uses of each structure, but only look inside of the
+        // first use, so that subtrees that are only repeated because their
+        // parent is are not shared on their own.
+        let mut uses = vec![0; interner.sizes.len()];
+        let mut stack = vec![tree];
+
+        while let Some(tree) = stack.pop() {
+            let children = match (interner.ids.get(&std::ptr::from_ref(tree)), tree) {
+                (Some(&id), _) => {
+                    uses[id] += 1;
+                    if uses[id] > 1 {
+                        continue;
+                    }
+                    widget(tree).and_then(|(_, _, children, _)| children).unwrap_or_default()
+                }
+                (None, InternalTree::Enter { children, .. }) => children,
+                (None, InternalTree::DefWindow { node, .. }) => std::slice::from_ref(node.as_ref()),
+                (None, _) => &[],
+            };
+
+            stack.extend(children.iter().rev());
+        }
+
+        let ids = interner
+            .ids
+            .into_iter()
+            .filter(|&(_, id)| uses[id] > 1 && interner.sizes[id] >= MIN_SHARED_SIZE)
+            .collect();
+
+        SharedSubtrees { ids }
+    }
+
+    /// The id of `tree` if it is shared.
+    pub fn id(&self, tree: &InternalTree<'a>) -> Option<usize> {
+        self.ids.get(&std::ptr::from_ref(tree)).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FileInfo, TranspileOptions, parse, to_internal_tree, transpile};
+
+    /// A column of two labels, which is large enough to be shared.
+    const COLUMN: &str = r#"<Box orientation="v"><Label text="a"/><Label text="b"/></Box>"#;
+
+    fn root(windows: &[&str]) -> String {
+        let windows: String = windows
+            .iter()
+            .enumerate()
+            .map(|(i, body)| format!(r#"<Window name="w{}">{}</Window>"#, i, body))
+            .collect();
+        format!("<Root>{}</Root>", windows)
+    }
+
+    /// The children of the widget at the root of each window.
+    fn window_children<'t, 'a>(tree: &'t InternalTree<'a>) -> Vec<&'t [InternalTree<'a>]> {
+        let InternalTree::Enter { children, .. } = tree else { panic!("not a root") };
+
+        children
+            .iter()
+            .map(|window| match window {
+                InternalTree::DefWindow { node, .. } => widget(node).unwrap().2.unwrap(),
+                _ => panic!("not a window"),
+            })
+            .collect()
+    }
+
+    fn generate(script: &str, split_windows: bool) -> (String, Vec<String>) {
+        let options = TranspileOptions { split_windows, ..Default::default() };
+        let generated = transpile(&FileInfo { file_path: "test.xml", script }, &options).unwrap();
+
+        (generated.code, generated.modules.into_iter().map(|(_, code)| code).collect())
+    }
+
+    #[test]
+    fn equal_subtrees_share_an_id() {
+        let script = root(&[&format!(
+            r#"<Box>{}{}<Box orientation="h"><Label text="a"/><Label text="b"/></Box></Box>"#,
+            COLUMN, COLUMN
+        )]);
+        let options = TranspileOptions::default();
+        let ast = parse(&FileInfo { file_path: "test.xml", script: &script }).unwrap();
+        let tree = to_internal_tree(&ast, &options).unwrap();
+
+        let shared = SharedSubtrees::find(&tree);
+        let children = window_children(&tree)[0];
+
+        // Each use is keyed by its own address, but both have the same id
+        assert!(shared.id(&children[0]).is_some());
+        assert_eq!(shared.id(&children[0]), shared.id(&children[1]));
+        // The same widgets with other attributes are a different structure
+        assert_eq!(shared.id(&children[2]), None);
+        // Only the subtree itself is shared, not the labels inside of it
+        let labels = widget(&children[0]).unwrap().2.unwrap();
+        assert_eq!(shared.id(&labels[0]), None);
+    }
+
+    #[test]
+    fn small_subtrees_are_not_shared() {
+        let small = r#"<Box><Label text="a"/></Box>"#;
+        let script = root(&[&format!("<Box>{}{}{}</Box>", small, small, small)]);
+        let options = TranspileOptions::default();
+        let ast = parse(&FileInfo { file_path: "test.xml", script: &script }).unwrap();
+        let tree = to_internal_tree(&ast, &options).unwrap();
+
+        let shared = SharedSubtrees::find(&tree);
+        assert!(window_children(&tree)[0].iter().all(|child| shared.id(child).is_none()));
+    }
+
+    #[test]
+    fn nested_shared_subtrees_are_numbered_inside_out() {
+        let row = format!(r#"<Box>{}<Label text="c"/></Box>"#, COLUMN);
+        let script = root(&[&format!("<Box>{}{}</Box>", row, row), COLUMN]);
+
+        let (code, _) = generate(&script, false);
+
+        // The column is shared on its own because the second window uses it too
+        let column = code.find("fn shared_0()").unwrap();
+        let row = code.find("fn shared_1()").unwrap();
+        assert!(column < row, "{}", code);
+        assert!(code[row..].contains("shared_0(),"), "{}", code);
+        assert!(!code.contains("fn shared_2()"), "{}", code);
+    }
+
+    #[test]
+    fn repeated_subtrees_snapshot() {
+        let script = root(&[&format!("<Box>{}{}</Box>", COLUMN, COLUMN), COLUMN]);
+
+        let shared_0 = concat!(
+            "fn shared_0() {\n",
+            "  box(#{ \"orientation\": `v` }, [\n",
+            "    label(#{ \"text\": `a` }),\n",
+            "    label(#{ \"text\": `b` })\n",
+            "  ])\n",
+            "}\n",
+        );
+        let w0_child = concat!(
+            "fn w0_child() {\n",
+            "  box(#{}, [\n",
+            "    shared_0(),\n",
+            "    shared_0()\n",
+            "  ])\n",
+            "}\n",
+        );
+
+        let (code, modules) = generate(&script, false);
+        let expected = format!(
+            "{}\n{}\nfn w1_child() {{\n  shared_0()\n}}\n\n{}",
+            shared_0,
+            w0_child,
+            concat!(
+                "enter([\n",
+                "  defwindow(\"w0\", #{}, w0_child()),\n",
+                "  defwindow(\"w1\", #{}, w1_child())\n",
+                "])\n",
+            ),
+        );
+        assert_eq!(code, expected);
+        assert!(modules.is_empty());
+
+        // Split windows only share subtrees inside of their own module
+        let (code, modules) = generate(&script, true);
+        assert!(!code.contains("shared_"), "{}", code);
+        assert_eq!(modules[0], format!("{}\n{}", shared_0, w0_child));
+        assert_eq!(
+            modules[1],
+            concat!(
+                "fn w1_child() {\n",
+                "  box(#{ \"orientation\": `v` }, [\n",
+                "    label(#{ \"text\": `a` }),\n",
+                "    label(#{ \"text\": `b` })\n",
+                "  ])\n",
+                "}\n",
+            )
+        );
+    }
+}
@@ -1,8 +1,23 @@
 mod ast_to_tree;
+mod backend;
+mod dedupe;
+mod print_rhai;
+mod rhai_ir;
 mod tree;
 mod tree_to_rhai;
+mod tree_to_xml;
+mod tree_to_yuck;
+mod verify;
 
 // Reexports
-pub use ast_to_tree::node_to_internal_tree as convert_node;
-pub use tree::{Attr, InternalTree};
-pub use tree_to_rhai::internal_tree_to_rhai as convert_tree;
+pub use ast_to_tree::{node_to_internal_tree as convert_node, widget_node};
+pub use backend::{Backend, Generated, RhaiBackend, Target, YuckBackend};
+pub use print_rhai::{Indent, RhaiStyle, print_rhai};
+pub use rhai_ir::{Expr, Item, Script};
+pub use tree::{Attr, InternalTree, Span};
+pub use tree_to_rhai::{
+    internal_tree_to_rhai as convert_tree, internal_tree_to_rhai_ir as convert_tree_to_rhai_ir,
+};
+pub use tree_to_xml::internal_tree_to_xml as convert_tree_to_xml;
+pub use tree_to_yuck::internal_tree_to_yuck as convert_tree_to_yuck;
+pub use verify::{position_span, verify_rhai};
@@ -0,0 +1,399 @@
+use super::rhai_ir::{Expr, Item, Script};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+/// How the generated Rhai code is laid out.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RhaiStyle {
+    /// Indentation of a single level.
+    pub indent: Indent,
+    /// Maps and arrays are put on a single line when it fits in this
+    /// many columns, and expanded otherwise. If `None`, maps are only put
+    /// on a single line when they have one entry, and arrays never are.
+    pub max_width: Option<usize>,
+    /// Put a comma after the last entry of expanded maps and arrays.
+    pub trailing_commas: bool,
+    /// Number of blank lines between functions.
+    pub blank_lines: usize,
+    /// Leave out all optional whitespace, and shorten the
+    /// names of the generated functions. The other settings are ignored.
+    pub minify: bool,
+}
+
+impl Default for RhaiStyle {
+    fn default() -> Self {
+        RhaiStyle {
+            indent: Indent::Spaces(2),
+            max_width: None,
+            trailing_commas: false,
+            blank_lines: 1,
+            minify: false,
+        }
+    }
+}
+
+/// [`Indent`] is a single level of indentation.
+/// It is written as a number of spaces, or as `tab`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(try_from = "IndentValue")]
+pub enum Indent {
+    Spaces(usize),
+    Tab,
+}
+
+/// Columns that a tab counts as when lines are measured.
+const TAB_WIDTH: usize = 4;
+
+/// How an [`Indent`] is written in `xmlman.toml`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum IndentValue {
+    Width(usize),
+    Name(String),
+}
+
+impl TryFrom<IndentValue> for Indent {
+    type Error = String;
+
+    fn try_from(value: IndentValue) -> Result<Self, Self::Error> {
+        match value {
+            IndentValue::Width(width) => Ok(Indent::Spaces(width)),
+            IndentValue::Name(name) => name.parse(),
+        }
+    }
+}
+
+impl FromStr for Indent {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tab" => Ok(Indent::Tab),
+            s => s
+                .parse()
+                .map(Indent::Spaces)
+                .map_err(|_| format!("expected a number of spaces or `tab`, found `{}`", s)),
+        }
+    }
+}
+
+/// Lay out the script in `style`.
+pub fn print_rhai(script: &Script, style: &RhaiStyle) -> String {
+    let mut printer = Printer { style, names: HashMap::new(), out: String::new() };
+
+    if style.minify {
+        return printer.minified(script);
+    }
+
+    let separator = "\n".repeat(style.blank_lines + 1);
+
+    for (i, item) in script.items.iter().enumerate() {
+        // Imports stick to each other
+        if i > 0 {
+            match (&script.items[i - 1], item) {
+                (Item::Import { .. }, Item::Import { .. }) => printer.out.push('\n'),
+                _ => printer.out.push_str(&separator),
+            }
+        }
+
+        printer.item(item);
+    }
+
+    printer.out.push('\n');
+    printer.out
+}
+
+struct Printer<'s> {
+    style: &'s RhaiStyle,
+    /// Short names of the generated functions, when minifying.
+    names: HashMap<&'s str, String>,
+    out: String,
+}
+
+impl<'s> Printer<'s> {
+    /// The whole script without any optional whitespace.
+    fn minified(mut self, script: &'s Script) -> String {
+        let (mut calls, mut words) = (HashSet::new(), HashSet::new());
+        for item in &script.items {
+            if let Item::Fn { body: expr, .. } | Item::Expr(expr) = item {
+                used_names(expr, &mut calls, &mut words);
+            }
+        }
+
+        // Short names must not shadow anything that the script uses. Only
+        // functions that are called by the script itself are renamed, as
+        // other scripts might call the rest from a module.
+        let mut short_names = (0..)
+            .map(|i| format!("f{}", i))
+            .filter(|n| !calls.contains(n.as_str()) && !words.contains(n.as_str()));
+        for item in &script.items {
+            if let Item::Fn { name, .. } = item
+                && calls.contains(name.as_str())
+                && !words.contains(name.as_str())
+            {
+                self.names.insert(name, short_names.next().unwrap_or_default());
+            }
+        }
+
+        for item in &script.items {
+            match item {
+                Item::Fn { name, body } => {
+                    let body = self.flat(body);
+                    self.out.push_str(&format!("fn {}(){{{}}}", self.name(name), body));
+                }
+                Item::Import { path, alias } => {
+                    self.out.push_str(&format!("import {} as {};", string_literal(path), alias))
+                }
+                Item::Expr(expr) => {
+                    let expr = self.flat(expr);
+                    self.out.push_str(&expr);
+                }
+            }
+        }
+
+        self.out
+    }
+
+    fn item(&mut self, item: &Item) {
+        match item {
+            Item::Fn { name, body } => {
+                self.out.push_str(&format!("fn {}() {{", name));
+                self.newline(1);
+                self.expr(body, 1);
+                self.newline(0);
+                self.out.push('}');
+            }
+            Item::Import { path, alias } => {
+                self.out.push_str(&format!("import {} as {};", string_literal(path), alias))
+            }
+            Item::Expr(expr) => self.expr(expr, 0),
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr, level: usize) {
+        match expr {
+            Expr::Call { name, args, .. } => {
+                self.out.push_str(name);
+                self.out.push('(');
+
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str(", ");
+                    }
+                    self.expr(arg, level);
+                }
+
+                self.out.push(')');
+            }
+            Expr::Map(entries) if !entries.is_empty() && !self.inline(expr, entries.len() == 1) => {
+                self.out.push_str("#{");
+
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push(',');
+                    }
+                    self.newline(level + 1);
+                    self.out.push_str(&format!("{}: ", string_literal(key)));
+                    self.expr(value, level + 1);
+                }
+
+                self.close(level, '}');
+            }
+            Expr::Array(items) if !items.is_empty() && !self.inline(expr, false) => {
+                self.out.push('[');
+
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push(',');
+                    }
+                    self.newline(level + 1);
+                    self.expr(item, level + 1);
+                }
+
+                self.close(level, ']');
+            }
+            expr => {
+                let expr = self.flat(expr);
+                self.out.push_str(&expr);
+            }
+        }
+    }
+
+    /// Whether `expr` should be put on a single line.
+    /// `default` is used when there is no maximum width.
+    fn inline(&self, expr: &Expr, default: bool) -> bool {
+        match self.style.max_width {
+            Some(max) => self.column() + self.flat(expr).chars().count() <= max,
+            None => default,
+        }
+    }
+
+    /// Close an expanded map or array.
+    fn close(&mut self, level: usize, bracket: char) {
+        if self.style.trailing_commas {
+            self.out.push(',');
+        }
+        self.newline(level);
+        self.out.push(bracket);
+    }
+
+    fn newline(&mut self, level: usize) {
+        self.out.push('\n');
+
+        match self.style.indent {
+            Indent::Spaces(width) => self.out.push_str(&" ".repeat(width * level)),
+            Indent::Tab => self.out.push_str(&"\t".repeat(level)),
+        }
+    }
+
+    /// Column that the next character is printed at.
+    fn column(&self) -> usize {
+        let line = self.out.rsplit('\n').next().unwrap_or_default();
+        line.chars().map(|c| if c == '\t' { TAB_WIDTH } else { 1 }).sum()
+    }
+
+    /// The name that a function is called by.
+    fn name<'n>(&'n self, name: &'n str) -> &'n str {
+        self.names.get(name).map_or(name, String::as_str)
+    }
+
+    /// `expr` on a single line.
+    fn flat(&self, expr: &Expr) -> String {
+        let (comma, colon, pad) =
+            if self.style.minify { (",", ":", "") } else { (", ", ": ", " ") };
+
+        match expr {
+            Expr::Call { name, args, .. } => {
+                let args: Vec<String> = args.iter().map(|arg| self.flat(arg)).collect();
+                format!("{}({})", self.name(name), args.join(comma))
+            }
+            Expr::Map(entries) if entries.is_empty() => "#{}".to_string(),
+            Expr::Map(entries) => {
+                let entries: Vec<String> = entries
+                    .iter()
+                    .map(|(k, v)| format!("{}{}{}", string_literal(k), colon, self.flat(v)))
+                    .collect();
+                format!("#{{{}{}{}}}", pad, entries.join(comma), pad)
+            }
+            Expr::Array(items) => {
+                let items: Vec<String> = items.iter().map(|item| self.flat(item)).collect();
+                format!("[{}]", items.join(comma))
+            }
+            Expr::String(s) => string_literal(s),
+            Expr::Template(s) => format!("`{}`", s),
+            Expr::Code(code) => code.clone(),
+        }
+    }
+}
+
+/// Collect the names of the functions that `expr` calls, and every word
+/// in its code and templates, which might be calls too.
+fn used_names<'e>(expr: &'e Expr, calls: &mut HashSet<&'e str>, words: &mut HashSet<&'e str>) {
+    match expr {
+        Expr::Call { name, args, .. } => {
+            calls.insert(name);
+            args.iter().for_each(|arg| used_names(arg, calls, words));
+        }
+        Expr::Map(entries) => entries.iter().for_each(|(_, v)| used_names(v, calls, words)),
+        Expr::Array(items) => items.iter().for_each(|item| used_names(item, calls, words)),
+        Expr::Code(code) | Expr::Template(code) => {
+            words.extend(code.split(|c: char| !c.is_alphanumeric() && c != '_'))
+        }
+        Expr::String(_) => {}
+    }
+}
+
+fn string_literal(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rhai::{Dynamic, Engine};
+
+    fn call(name: &str, args: Vec<Expr>) -> Expr {
+        Expr::Call { name: name.to_string(), args, span: None }
+    }
+
+    fn map(key: &str, value: Expr) -> Expr {
+        Expr::Map(vec![(key.to_string(), value)])
+    }
+
+    /// Run `code` with widget functions that describe their call.
+    fn run(code: &str) -> String {
+        let mut engine = Engine::new();
+        engine.set_max_expr_depths(0, 0);
+        engine.register_fn("f0", || "native f0".to_string());
+        for name in ["box", "label", "enter", "defwindow"] {
+            engine.register_fn(name, move |a: Dynamic| format!("{}({:?})", name, a));
+            engine.register_fn(name, move |a: Dynamic, b: Dynamic| {
+                format!("{}({:?}, {:?})", name, a, b)
+            });
+            engine.register_fn(name, move |a: Dynamic, b: Dynamic, c: Dynamic| {
+                format!("{}({:?}, {:?}, {:?})", name, a, b, c)
+            });
+        }
+        engine.eval::<String>(code).unwrap()
+    }
+
+    fn script() -> Script {
+        let label = |text: &str| call("label", vec![map("text", Expr::Template(text.into()))]);
+        let window = call(
+            "box",
+            vec![
+                map("class", Expr::String("a \"b\"".into())),
+                Expr::Array(vec![
+                    label("${helper()}"),
+                    call("f0", Vec::new()),
+                    call("shared_0", Vec::new()),
+                    call("shared_0", Vec::new()),
+                ]),
+            ],
+        );
+
+        Script {
+            items: vec![
+                Item::Fn { name: "helper".into(), body: Expr::String("help".into()) },
+                Item::Fn { name: "shared_0".into(), body: label("shared") },
+                Item::Fn { name: "bar_child".into(), body: window },
+                Item::Fn { name: "exported".into(), body: Expr::Code("1 + 2".into()) },
+                Item::Expr(call(
+                    "enter",
+                    vec![Expr::Array(vec![call(
+                        "defwindow",
+                        vec![
+                            Expr::String("bar".into()),
+                            Expr::Map(Vec::new()),
+                            call("bar_child", Vec::new()),
+                        ],
+                    )])],
+                )),
+            ],
+        }
+    }
+
+    #[test]
+    fn minified_output_does_the_same() {
+        let script = script();
+        let pretty = print_rhai(&script, &RhaiStyle::default());
+        let minified = print_rhai(&script, &RhaiStyle { minify: true, ..RhaiStyle::default() });
+
+        assert!(!minified.contains('\n'));
+        assert_eq!(run(&pretty), run(&minified));
+    }
+
+    #[test]
+    fn minify_renames_only_called_functions() {
+        let minified = print_rhai(&script(), &RhaiStyle { minify: true, ..RhaiStyle::default() });
+
+        // `f0` is called by the script, so the short names start at `f1`
+        assert!(minified.contains("fn f1(){label("));
+        assert!(minified.contains("fn f2(){box("));
+        assert!(minified.contains(",f0(),f1(),f1()]"));
+        // Functions that are used in templates or not called keep their name
+        assert!(minified.contains("fn helper(){"));
+        assert!(minified.contains("fn exported(){"));
+    }
+}
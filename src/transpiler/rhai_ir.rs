@@ -0,0 +1,46 @@
+//! A small IR of the generated Rhai code.
+//!
+//! [`internal_tree_to_rhai`](super::convert_tree) lowers the internal tree
+//! into a [`Script`], and [`print_rhai`](super::print_rhai) lays it out.
+//! Keeping the two apart means that every formatting decision is made in
+//! the printer, in one place.
+
+use std::ops::Range;
+
+/// [`Script`] is a whole Rhai script.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Script {
+    pub items: Vec<Item>,
+}
+
+/// A top-level item of a [`Script`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Item {
+    /// A function without parameters that returns `body`.
+    Fn { name: String, body: Expr },
+    /// An import of the module at `path`, without its extension.
+    Import { path: String, alias: String },
+    /// An expression statement, like the call to `enter`.
+    Expr(Expr),
+}
+
+/// An expression in the generated code.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    /// A function call. `span` is the xml element that it was generated from.
+    Call {
+        name: String,
+        args: Vec<Expr>,
+        span: Option<Range<usize>>,
+    },
+    /// An object map literal, with its keys in order.
+    Map(Vec<(String, Expr)>),
+    Array(Vec<Expr>),
+    /// A string literal, which is escaped when it is printed.
+    String(String),
+    /// A template string literal. Attribute values are written as these,
+    /// so `${var}` in an attribute keeps working.
+    Template(String),
+    /// Code that is printed as it is, from `@no_quote`.
+    Code(String),
+}
@@ -2,10 +2,14 @@
 // after converting ast to tree.
 #![allow(dead_code)]
 
+use std::borrow::Cow;
+
+/// An attribute of an element. Unless a define was expanded
+/// in it, it borrows from the [`Ast`](crate::parser::Ast).
 #[derive(Debug, Clone)]
-pub struct Attr {
-    pub key: String,
-    pub value: String,
+pub struct Attr<'a> {
+    pub key: Cow<'a, str>,
+    pub value: Cow<'a, str>,
 }
 
 #[derive(Debug, Clone)]
@@ -26,35 +30,136 @@ impl Span {
 }
 
 #[derive(Debug, Clone)]
-pub enum InternalTree {
+pub enum InternalTree<'a> {
     // === Widgets === //
-    Label { attrs: Vec<Attr>, span: Span },
-    Box { attrs: Vec<Attr>, children: Vec<InternalTree>, span: Span },
-    CenterBox { attrs: Vec<Attr>, children: Vec<InternalTree>, span: Span },
-    Button { attrs: Vec<Attr>, span: Span },
-    Image { attrs: Vec<Attr>, span: Span },
-    Input { attrs: Vec<Attr>, span: Span },
-    Progress { attrs: Vec<Attr>, span: Span },
-    ComboBoxText { attrs: Vec<Attr>, span: Span },
-    Slider { attrs: Vec<Attr>, span: Span },
-    Checkbox { attrs: Vec<Attr>, span: Span },
-    Expander { attrs: Vec<Attr>, children: Vec<InternalTree>, span: Span },
-    Revealer { attrs: Vec<Attr>, children: Vec<InternalTree>, span: Span },
-    Scroll { attrs: Vec<Attr>, children: Vec<InternalTree>, span: Span },
-    OverLay { attrs: Vec<Attr>, children: Vec<InternalTree>, span: Span },
-    Stack { attrs: Vec<Attr>, children: Vec<InternalTree>, span: Span },
-    Calendar { attrs: Vec<Attr>, span: Span },
-    ColorButton { attrs: Vec<Attr>, span: Span },
-    ColorChooser { attrs: Vec<Attr>, span: Span },
-    CircularProgress { attrs: Vec<Attr>, span: Span },
-    Graph { attrs: Vec<Attr>, span: Span },
-    Transform { attrs: Vec<Attr>, span: Span },
-    EventBox { attrs: Vec<Attr>, children: Vec<InternalTree>, span: Span },
-    ToolTip { attrs: Vec<Attr>, children: Vec<InternalTree>, span: Span },
+    Label {
+        attrs: Vec<Attr<'a>>,
+        span: Span,
+    },
+    Box {
+        attrs: Vec<Attr<'a>>,
+        children: Vec<InternalTree<'a>>,
+        span: Span,
+    },
+    CenterBox {
+        attrs: Vec<Attr<'a>>,
+        children: Vec<InternalTree<'a>>,
+        span: Span,
+    },
+    Button {
+        attrs: Vec<Attr<'a>>,
+        span: Span,
+    },
+    Image {
+        attrs: Vec<Attr<'a>>,
+        span: Span,
+    },
+    Input {
+        attrs: Vec<Attr<'a>>,
+        span: Span,
+    },
+    Progress {
+        attrs: Vec<Attr<'a>>,
+        span: Span,
+    },
+    ComboBoxText {
+        attrs: Vec<Attr<'a>>,
+        span: Span,
+    },
+    Slider {
+        attrs: Vec<Attr<'a>>,
+        span: Span,
+    },
+    Checkbox {
+        attrs: Vec<Attr<'a>>,
+        span: Span,
+    },
+    Expander {
+        attrs: Vec<Attr<'a>>,
+        children: Vec<InternalTree<'a>>,
+        span: Span,
+    },
+    Revealer {
+        attrs: Vec<Attr<'a>>,
+        children: Vec<InternalTree<'a>>,
+        span: Span,
+    },
+    Scroll {
+        attrs: Vec<Attr<'a>>,
+        children: Vec<InternalTree<'a>>,
+        span: Span,
+    },
+    OverLay {
+        attrs: Vec<Attr<'a>>,
+        children: Vec<InternalTree<'a>>,
+        span: Span,
+    },
+    Stack {
+        attrs: Vec<Attr<'a>>,
+        children: Vec<InternalTree<'a>>,
+        span: Span,
+    },
+    Calendar {
+        attrs: Vec<Attr<'a>>,
+        span: Span,
+    },
+    ColorButton {
+        attrs: Vec<Attr<'a>>,
+        span: Span,
+    },
+    ColorChooser {
+        attrs: Vec<Attr<'a>>,
+        span: Span,
+    },
+    CircularProgress {
+        attrs: Vec<Attr<'a>>,
+        span: Span,
+    },
+    Graph {
+        attrs: Vec<Attr<'a>>,
+        span: Span,
+    },
+    Transform {
+        attrs: Vec<Attr<'a>>,
+        span: Span,
+    },
+    EventBox {
+        attrs: Vec<Attr<'a>>,
+        children: Vec<InternalTree<'a>>,
+        span: Span,
+    },
+    ToolTip {
+        attrs: Vec<Attr<'a>>,
+        children: Vec<InternalTree<'a>>,
+        span: Span,
+    },
+    /// A widget from the `[widgets]` table of `xmlman.toml`.
+    Custom {
+        function: Cow<'a, str>,
+        attrs: Vec<Attr<'a>>,
+        children: Vec<InternalTree<'a>>,
+        span: Span,
+    },
 
     // === Top-level macros === //
-    DefWindow { name: String, attrs: Vec<Attr>, node: Box<InternalTree>, span: Span },
-    Poll { var: String, attrs: Vec<Attr>, span: Span },
-    Listen { var: String, attrs: Vec<Attr>, span: Span },
-    Enter { children: Vec<InternalTree>, span: Span },
+    DefWindow {
+        name: Cow<'a, str>,
+        attrs: Vec<Attr<'a>>,
+        node: Box<InternalTree<'a>>,
+        span: Span,
+    },
+    Poll {
+        var: Cow<'a, str>,
+        attrs: Vec<Attr<'a>>,
+        span: Span,
+    },
+    Listen {
+        var: Cow<'a, str>,
+        attrs: Vec<Attr<'a>>,
+        span: Span,
+    },
+    Enter {
+        children: Vec<InternalTree<'a>>,
+        span: Span,
+    },
 }
@@ -1,252 +1,325 @@
-use super::{Attr, InternalTree};
+use super::dedupe::SharedSubtrees;
+use super::print_rhai::{RhaiStyle, print_rhai};
+use super::rhai_ir::{Expr, Item, Script};
+use super::{Attr, InternalTree, Span};
 use crate::error::DiagInfo;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 
-fn format_value(value: &str) -> String {
-    if let Some(inner) = value.strip_prefix("@no_quote(").and_then(|s| s.strip_suffix(")")) {
-        inner.to_string()
-    } else {
-        format!("`{}`", value)
-    }
+/// Properties of `defwindow` that are grouped into a nested map,
+/// from the prefix of their attributes to the keys that they can have.
+const WINDOW_MAPS: [(&str, &[&str]); 2] =
+    [("geometry", &["x", "y", "anchor", "width", "height"]), ("reserve", &["side", "distance"])];
+
+/// Generate a Rhai script from the tree, laid out in `style`.
+pub fn internal_tree_to_rhai(
+    tree: &InternalTree,
+    style: &RhaiStyle,
+) -> Result<String, DiagInfo<'static>> {
+    Ok(print_rhai(&internal_tree_to_rhai_ir(tree)?, style))
 }
 
-/// Format attributes into a Rhai-compatible map with pretty indentation
-fn format_attrs(attrs: &Vec<Attr>, indent: usize) -> String {
-    if attrs.is_empty() {
-        return "#{}".to_string();
-    }
+/// Directory that the modules of the windows are imported from.
+pub const WINDOWS_DIR: &str = "windows";
 
-    let indent_str = "  ".repeat(indent);
-    let entries: Vec<String> =
-        attrs.iter().map(|a| format!("\"{}\": {}", a.key, format_value(&a.value))).collect();
-
-    if entries.len() == 1 {
-        format!("#{{ {} }}", entries[0])
-    } else {
-        let inner = entries
-            .into_iter()
-            .map(|e| format!("{}  {}", indent_str, e))
-            .collect::<Vec<_>>()
-            .join(",\n");
-        format!("#{{\n{}\n{}}}", inner, indent_str)
-    }
+/// Lower the tree into a [`Script`]. The functions of the windows and
+/// of repeated subtrees come first, and the call to `enter` last.
+pub fn internal_tree_to_rhai_ir(tree: &InternalTree) -> Result<Script, DiagInfo<'static>> {
+    let mut lowering = Lowering::new(tree, None);
+    let enter = lowering.lower(tree, true)?;
+
+    lowering.items.push(Item::Expr(enter));
+    Ok(Script { items: lowering.items })
 }
 
-/// Special-case attribute parser for `defwindow`
-fn defwidget_attrs_parser(attrs: &Vec<Attr>) -> String {
-    let mut flat_entries: Vec<String> = Vec::new();
+/// Lower the tree into an entry [`Script`], and a module for each window.
+/// The entry imports the modules, which are returned by their import path.
+/// Repeated subtrees are only shared inside of a window, so that every
+/// module can be used on its own.
+pub fn internal_tree_to_rhai_modules(
+    tree: &InternalTree,
+) -> Result<(Script, Vec<(String, Script)>), DiagInfo<'static>> {
+    let mut lowering = Lowering::new(tree, Some(Vec::new()));
+    let enter = lowering.lower(tree, true)?;
 
-    let mut geometry_map: BTreeMap<String, String> = BTreeMap::new();
-    let mut reserve_map: BTreeMap<String, String> = BTreeMap::new();
+    lowering.items.push(Item::Expr(enter));
+    Ok((Script { items: lowering.items }, lowering.modules.unwrap_or_default()))
+}
 
-    for a in attrs {
-        if let Some(stripped) = a.key.strip_prefix("geometry.") {
-            match stripped {
-                "x" | "y" | "anchor" | "width" | "height" => {
-                    geometry_map.insert(stripped.to_string(), a.value.clone());
-                    continue;
-                }
-                _ => {}
-            }
-        }
+fn value(value: &str) -> Expr {
+    match value.strip_prefix("@no_quote(").and_then(|s| s.strip_suffix(")")) {
+        Some(code) => Expr::Code(code.to_string()),
+        None => Expr::Template(value.to_string()),
+    }
+}
 
-        if let Some(stripped) = a.key.strip_prefix("reserve.") {
-            match stripped {
-                "side" | "distance" => {
-                    reserve_map.insert(stripped.to_string(), a.value.clone());
-                    continue;
-                }
-                _ => {}
+fn attrs_map(attrs: &[Attr]) -> Expr {
+    Expr::Map(attrs.iter().map(|a| (a.key.to_string(), value(&a.value))).collect())
+}
+
+/// The properties of a window, with the geometry and reserve grouped into maps.
+fn window_map(attrs: &[Attr]) -> Expr {
+    let mut entries = Vec::new();
+    let mut maps: [BTreeMap<&str, &str>; 2] = Default::default();
+
+    'attrs: for a in attrs {
+        for ((prefix, keys), map) in WINDOW_MAPS.iter().zip(&mut maps) {
+            if let Some(key) = a.key.strip_prefix(prefix).and_then(|k| k.strip_prefix('.'))
+                && keys.contains(&key)
+            {
+                map.insert(key, &a.value);
+                continue 'attrs;
             }
         }
 
-        flat_entries.push(format!("\"{}\": {}", a.key, format_value(&a.value)));
+        entries.push((a.key.to_string(), value(&a.value)));
     }
 
-    if !geometry_map.is_empty() {
-        let geom_entries: Vec<String> = geometry_map
-            .into_iter()
-            .map(|(k, v)| format!("\"{}\": {}", k, format_value(&v)))
-            .collect();
-        flat_entries.push(format!("\"geometry\": #{{ {} }}", geom_entries.join(", ")));
+    for ((prefix, _), map) in WINDOW_MAPS.iter().zip(maps) {
+        if !map.is_empty() {
+            let map = map.into_iter().map(|(k, v)| (k.to_string(), value(v))).collect();
+            entries.push((prefix.to_string(), Expr::Map(map)));
+        }
     }
 
-    if !reserve_map.is_empty() {
-        let reserve_entries: Vec<String> = reserve_map
-            .into_iter()
-            .map(|(k, v)| format!("\"{}\": {}", k, format_value(&v)))
-            .collect();
-        flat_entries.push(format!("\"reserve\": #{{ {} }}", reserve_entries.join(", ")));
-    }
+    Expr::Map(entries)
+}
 
-    format!("#{{ {} }}", flat_entries.join(", "))
+fn call(name: &str, args: Vec<Expr>, span: &Span) -> Expr {
+    Expr::Call { name: name.to_string(), args, span: span.to_range() }
 }
 
-/// Top-level wrapper that produces a single Rhai script string
-pub fn internal_tree_to_rhai(tree: &InternalTree) -> Result<String, DiagInfo<'_>> {
-    let (rhai_fns, enter_call) = transpile(tree, true, 0)?;
+/// The rhai function, attributes, children and span of a widget, or `None`
+/// for the top-level macros. Children are `None` if the widget has none.
+pub(super) type Widget<'t, 'a> =
+    (&'t str, &'t [Attr<'a>], Option<&'t [InternalTree<'a>]>, &'t Span);
 
-    let mut script_parts = Vec::new();
-    script_parts.extend(rhai_fns);
-    script_parts.push(enter_call);
+pub(super) fn widget<'t, 'a>(tree: &'t InternalTree<'a>) -> Option<Widget<'t, 'a>> {
+    Some(match tree {
+        InternalTree::Enter { .. }
+        | InternalTree::DefWindow { .. }
+        | InternalTree::Poll { .. }
+        | InternalTree::Listen { .. } => return None,
 
-    Ok(script_parts.join("\n\n"))
+        // Containers with children
+        InternalTree::Box { attrs, children, span } => ("box", attrs, Some(children), span),
+        InternalTree::CenterBox { attrs, children, span } => {
+            ("centerbox", attrs, Some(children), span)
+        }
+        InternalTree::Expander { attrs, children, span } => {
+            ("expander", attrs, Some(children), span)
+        }
+        InternalTree::Revealer { attrs, children, span } => {
+            ("revealer", attrs, Some(children), span)
+        }
+        InternalTree::Scroll { attrs, children, span } => ("scroll", attrs, Some(children), span),
+        InternalTree::OverLay { attrs, children, span } => ("overlay", attrs, Some(children), span),
+        InternalTree::Stack { attrs, children, span } => ("stack", attrs, Some(children), span),
+        InternalTree::EventBox { attrs, children, span } => {
+            ("eventbox", attrs, Some(children), span)
+        }
+        InternalTree::ToolTip { attrs, children, span } => ("tooltip", attrs, Some(children), span),
+
+        // Custom widgets are containers only when they have children
+        InternalTree::Custom { function, attrs, children, span } => {
+            (function, attrs, Some(children.as_slice()).filter(|c| !c.is_empty()), span)
+        }
+
+        // Leaf widgets
+        InternalTree::Button { attrs, span } => ("button", attrs, None, span),
+        InternalTree::Label { attrs, span } => ("label", attrs, None, span),
+        InternalTree::Image { attrs, span } => ("image", attrs, None, span),
+        InternalTree::Input { attrs, span } => ("input", attrs, None, span),
+        InternalTree::Progress { attrs, span } => ("progress", attrs, None, span),
+        InternalTree::ComboBoxText { attrs, span } => ("comboboxtext", attrs, None, span),
+        InternalTree::Slider { attrs, span } => ("slider", attrs, None, span),
+        InternalTree::Checkbox { attrs, span } => ("checkbox", attrs, None, span),
+        InternalTree::Calendar { attrs, span } => ("calendar", attrs, None, span),
+        InternalTree::ColorButton { attrs, span } => ("colorbutton", attrs, None, span),
+        InternalTree::ColorChooser { attrs, span } => ("colorchooser", attrs, None, span),
+        InternalTree::CircularProgress { attrs, span } => ("circularprogress", attrs, None, span),
+        InternalTree::Graph { attrs, span } => ("graph", attrs, None, span),
+        InternalTree::Transform { attrs, span } => ("transform", attrs, None, span),
+    })
 }
 
-/// Recursive transpile function with pretty-printing
-fn transpile(
-    tree: &InternalTree,
-    is_root: bool,
-    indent: usize,
-) -> Result<(Vec<String>, String), DiagInfo<'_>> {
-    let indent_str = "  ".repeat(indent);
-
-    match tree {
-        InternalTree::Enter { children, .. } => {
-            let mut fns = Vec::new();
-            let mut calls = Vec::new();
-
-            for child in children {
-                let (mut child_fns, child_call) = transpile(child, true, indent + 1)?;
-                fns.append(&mut child_fns);
-                calls.push(format!("{}{}", "  ".repeat(indent + 1), child_call));
+struct Lowering<'a> {
+    /// Functions of the windows and shared subtrees.
+    items: Vec<Item>,
+    shared: SharedSubtrees<'a>,
+    /// Names of the shared functions that were generated, by the id of their subtree.
+    shared_names: HashMap<usize, String>,
+    /// Modules of the windows by their import path, if windows are split into modules.
+    modules: Option<Vec<(String, Script)>>,
+}
+
+impl<'a> Lowering<'a> {
+    fn new(tree: &InternalTree<'a>, modules: Option<Vec<(String, Script)>>) -> Self {
+        Lowering {
+            items: Vec::new(),
+            shared: SharedSubtrees::find(tree),
+            shared_names: HashMap::new(),
+            modules,
+        }
+    }
+
+    /// Lower a single element into an expression.
+    fn lower(&mut self, tree: &InternalTree<'a>, is_root: bool) -> Result<Expr, DiagInfo<'static>> {
+        let Some(widget) = widget(tree) else {
+            return self.lower_macro(tree, is_root);
+        };
+
+        let Some(id) = self.shared.id(tree) else {
+            return self.lower_widget(widget);
+        };
+
+        let name = match self.shared_names.get(&id) {
+            Some(name) => name.clone(),
+            None => {
+                // Shared subtrees inside of this one are numbered first,
+                // so that the functions are numbered in order
+                let body = self.lower_widget(widget)?;
+                let name = format!("shared_{}", self.shared_names.len());
+
+                self.shared_names.insert(id, name.clone());
+                self.items.push(Item::Fn { name: name.clone(), body });
+                name
             }
+        };
 
-            let formatted = format!("enter([\n{}\n{}])", calls.join(",\n"), indent_str);
+        Ok(call(&name, Vec::new(), widget.3))
+    }
 
-            Ok((fns, formatted))
-        }
+    fn lower_widget(
+        &mut self,
+        (name, attrs, children, span): Widget<'_, 'a>,
+    ) -> Result<Expr, DiagInfo<'static>> {
+        let mut args = vec![attrs_map(attrs)];
 
-        InternalTree::DefWindow { name, attrs, node, .. } => {
-            let (mut child_fns, child_call) = transpile(node, false, indent + 1)?;
-            let fn_name = format!("{}_child", name);
-            let fn_def =
-                format!("fn {}() {{\n{}  {}\n{}}}", fn_name, indent_str, child_call, indent_str);
-            child_fns.push(fn_def);
-
-            let call = format!(
-                "defwindow(\"{}\", {}, {}())",
-                name,
-                defwidget_attrs_parser(attrs),
-                fn_name
-            );
-            Ok((child_fns, call))
+        if let Some(children) = children {
+            let children =
+                children.iter().map(|child| self.lower(child, false)).collect::<Result<_, _>>()?;
+            args.push(Expr::Array(children));
         }
 
-        InternalTree::Poll { var, attrs, span } => {
-            if is_root {
-                Ok((vec![], format!("poll(\"{}\", {})", var, format_attrs(attrs, indent))))
-            } else {
-                Err(DiagInfo {
-                    message: "Orphan poll element found deep inside root".to_string(),
-                    label: Some("here"),
-                    note: Some("poll/listen elements should only be defined at the top of <Root>"),
-                    span: span.to_range(),
-                })
+        Ok(call(name, args, span))
+    }
+
+    fn lower_macro(
+        &mut self,
+        tree: &InternalTree<'a>,
+        is_root: bool,
+    ) -> Result<Expr, DiagInfo<'static>> {
+        match tree {
+            InternalTree::Enter { children, span } => {
+                let windows = children
+                    .iter()
+                    .map(|child| self.lower(child, true))
+                    .collect::<Result<_, _>>()?;
+
+                Ok(call("enter", vec![Expr::Array(windows)], span))
             }
-        }
 
-        InternalTree::Listen { var, attrs, span } => {
-            if is_root {
-                Ok((vec![], format!("listen(\"{}\", {})", var, format_attrs(attrs, indent))))
-            } else {
-                Err(DiagInfo {
-                    message: "Orphan listen element found deep inside root".to_string(),
-                    label: Some("here"),
-                    note: Some("poll/listen elements should only be defined at the top of <Root>"),
-                    span: span.to_range(),
-                })
+            InternalTree::DefWindow { name, attrs, node, span } => {
+                let mut fn_name = format!("{}_child", name);
+
+                if let Some(modules) = &mut self.modules {
+                    // The name is used as the file name and alias of the module
+                    if !rhai::is_valid_function_name(name) {
+                        return Err(DiagInfo {
+                            code: "TE07",
+                            message: format!("Invalid window name for a module: '{}'", name),
+                            label: Some("this window"),
+                            note: Some(
+                                "split windows must be named with letters, digits and underscores",
+                            ),
+                            span: span.to_range(),
+                        });
+                    }
+
+                    // The window and its shared subtrees go into its own module
+                    let mut window = Lowering::new(node, None);
+                    let body = window.lower(node, false)?;
+                    window.items.push(Item::Fn { name: fn_name.clone(), body });
+
+                    let path = format!("{}/{}", WINDOWS_DIR, name);
+                    modules.push((path.clone(), Script { items: window.items }));
+                    self.items.push(Item::Import { path, alias: name.to_string() });
+                    fn_name = format!("{}::{}", name, fn_name);
+                } else {
+                    let body = self.lower(node, false)?;
+                    self.items.push(Item::Fn { name: fn_name.clone(), body });
+                }
+
+                let args = vec![
+                    Expr::String(name.to_string()),
+                    window_map(attrs),
+                    Expr::Call { name: fn_name, args: Vec::new(), span: None },
+                ];
+                Ok(call("defwindow", args, span))
             }
-        }
 
-        // Containers with children
-        InternalTree::Box { attrs, children, .. } => {
-            transpile_children_container("box".to_string(), attrs, children, indent)
-        }
-        InternalTree::CenterBox { attrs, children, .. } => {
-            transpile_children_container("centerbox".to_string(), attrs, children, indent)
-        }
-        InternalTree::Expander { attrs, children, .. }
-        | InternalTree::Revealer { attrs, children, .. }
-        | InternalTree::Scroll { attrs, children, .. }
-        | InternalTree::OverLay { attrs, children, .. }
-        | InternalTree::Stack { attrs, children, .. }
-        | InternalTree::EventBox { attrs, children, .. }
-        | InternalTree::ToolTip { attrs, children, .. } => {
-            let name = format!("{:?}", tree).to_lowercase();
-            transpile_children_container(name, attrs, children, indent)
-        }
+            InternalTree::Poll { var, attrs, span } | InternalTree::Listen { var, attrs, span } => {
+                let name = match tree {
+                    InternalTree::Poll { .. } => "poll",
+                    _ => "listen",
+                };
 
-        // Leaf widgets
-        InternalTree::Button { attrs, .. } => {
-            Ok((vec![], format!("button({})", format_attrs(attrs, indent))))
-        }
-        InternalTree::Label { attrs, .. } => {
-            Ok((vec![], format!("label({})", format_attrs(attrs, indent))))
-        }
-        InternalTree::Image { attrs, .. } => {
-            Ok((vec![], format!("image({})", format_attrs(attrs, indent))))
-        }
-        InternalTree::Input { attrs, .. } => {
-            Ok((vec![], format!("input({})", format_attrs(attrs, indent))))
-        }
-        InternalTree::Progress { attrs, .. } => {
-            Ok((vec![], format!("progress({})", format_attrs(attrs, indent))))
-        }
-        InternalTree::ComboBoxText { attrs, .. } => {
-            Ok((vec![], format!("comboboxtext({})", format_attrs(attrs, indent))))
-        }
-        InternalTree::Slider { attrs, .. } => {
-            Ok((vec![], format!("slider({})", format_attrs(attrs, indent))))
-        }
-        InternalTree::Checkbox { attrs, .. } => {
-            Ok((vec![], format!("checkbox({})", format_attrs(attrs, indent))))
-        }
-        InternalTree::Calendar { attrs, .. } => {
-            Ok((vec![], format!("calendar({})", format_attrs(attrs, indent))))
-        }
-        InternalTree::ColorButton { attrs, .. } => {
-            Ok((vec![], format!("colorbutton({})", format_attrs(attrs, indent))))
-        }
-        InternalTree::ColorChooser { attrs, .. } => {
-            Ok((vec![], format!("colorchooser({})", format_attrs(attrs, indent))))
-        }
-        InternalTree::CircularProgress { attrs, .. } => {
-            Ok((vec![], format!("circularprogress({})", format_attrs(attrs, indent))))
-        }
-        InternalTree::Graph { attrs, .. } => {
-            Ok((vec![], format!("graph({})", format_attrs(attrs, indent))))
-        }
-        InternalTree::Transform { attrs, .. } => {
-            Ok((vec![], format!("transform({})", format_attrs(attrs, indent))))
+                if !is_root {
+                    return Err(DiagInfo {
+                        code: "TE04",
+                        message: format!("Orphan {} element found deep inside root", name),
+                        label: Some("here"),
+                        note: Some(
+                            "poll/listen elements should only be defined at the top of <Root>",
+                        ),
+                        span: span.to_range(),
+                    });
+                }
+
+                Ok(call(name, vec![Expr::String(var.to_string()), attrs_map(attrs)], span))
+            }
+
+            _ => unreachable!("widgets are lowered by lower_widget"),
         }
     }
 }
 
-/// Helper for containers with children
-fn transpile_children_container<'a>(
-    name: String,
-    attrs: &'a Vec<Attr>,
-    children: &'a Vec<InternalTree>,
-    indent: usize,
-) -> Result<(Vec<String>, String), DiagInfo<'a>> {
-    let indent_str = "  ".repeat(indent);
-    let mut fns = Vec::new();
-    let mut child_calls = Vec::new();
-
-    for child in children {
-        let (mut cf, cc) = transpile(child, false, indent + 1)?;
-        fns.append(&mut cf);
-        child_calls.push(format!("{}{}", "  ".repeat(indent + 1), cc));
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span() -> Span {
+        Span { start: Some(0), end: Some(1) }
+    }
+
+    fn window(name: &str) -> InternalTree<'_> {
+        let label = InternalTree::Label { attrs: Vec::new(), span: span() };
+        let window = InternalTree::DefWindow {
+            name: name.into(),
+            attrs: Vec::new(),
+            node: Box::new(label),
+            span: span(),
+        };
+        InternalTree::Enter { children: vec![window], span: span() }
     }
 
-    let call = format!(
-        "{}({}, [\n{}\n{}])",
-        name,
-        format_attrs(attrs, indent),
-        child_calls.join(",\n"),
-        indent_str
-    );
+    #[test]
+    fn windows_are_split_into_modules() {
+        let (entry, modules) = internal_tree_to_rhai_modules(&window("bar")).unwrap();
 
-    Ok((fns, call))
+        assert_eq!(
+            entry.items[0],
+            Item::Import { path: "windows/bar".into(), alias: "bar".into() }
+        );
+        assert_eq!(modules.len(), 1);
+        assert_eq!(modules[0].0, "windows/bar");
+    }
+
+    #[test]
+    fn split_window_names_must_be_identifiers() {
+        for name in ["my-bar", "../bar", "a/b", "fn", "1bar"] {
+            let err = internal_tree_to_rhai_modules(&window(name)).unwrap_err();
+            assert_eq!(err.code, "TE07", "{}", name);
+        }
+    }
 }
@@ -0,0 +1,90 @@
+use super::{Attr, InternalTree};
+use std::collections::BTreeMap;
+
+/// Serialize the tree back into xml, for `xmlman import`.
+/// `widgets` are the custom widgets of the project, from the xml
+/// element name to the rhai function, to name [`InternalTree::Custom`] nodes.
+///
+/// The xml is written without any indentation, format it to make it readable.
+pub fn internal_tree_to_xml(tree: &InternalTree, widgets: &BTreeMap<String, String>) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\n");
+    write_tree(tree, widgets, &mut out);
+    out
+}
+
+fn write_tree(tree: &InternalTree, widgets: &BTreeMap<String, String>, out: &mut String) {
+    let (name, name_attr, attrs, children): (&str, _, &[Attr], &[InternalTree]) = match tree {
+        InternalTree::Enter { children, .. } => ("Root", None, &[], children),
+        InternalTree::DefWindow { name, attrs, node, .. } => {
+            ("Window", Some(name), attrs, std::slice::from_ref(node.as_ref()))
+        }
+        InternalTree::Poll { attrs, .. } => ("Poll", None, attrs, &[]),
+        InternalTree::Listen { attrs, .. } => ("Listen", None, attrs, &[]),
+        InternalTree::Custom { function, attrs, children, .. } => {
+            let name = widgets
+                .iter()
+                .find(|(_, f)| *f == function)
+                .map_or(function.as_ref(), |(name, _)| name.as_str());
+            (name, None, attrs, children)
+        }
+        InternalTree::Box { attrs, children, .. } => ("Box", None, attrs, children),
+        InternalTree::CenterBox { attrs, children, .. } => ("CenterBox", None, attrs, children),
+        InternalTree::Expander { attrs, children, .. } => ("Expander", None, attrs, children),
+        InternalTree::Revealer { attrs, children, .. } => ("Revealer", None, attrs, children),
+        InternalTree::Scroll { attrs, children, .. } => ("Scroll", None, attrs, children),
+        InternalTree::OverLay { attrs, children, .. } => ("OverLay", None, attrs, children),
+        InternalTree::Stack { attrs, children, .. } => ("Stack", None, attrs, children),
+        InternalTree::EventBox { attrs, children, .. } => ("EventBox", None, attrs, children),
+        InternalTree::ToolTip { attrs, children, .. } => ("ToolTip", None, attrs, children),
+        InternalTree::Label { attrs, .. } => ("Label", None, attrs, &[]),
+        InternalTree::Button { attrs, .. } => ("Button", None, attrs, &[]),
+        InternalTree::Image { attrs, .. } => ("Image", None, attrs, &[]),
+        InternalTree::Input { attrs, .. } => ("Input", None, attrs, &[]),
+        InternalTree::Progress { attrs, .. } => ("Progress", None, attrs, &[]),
+        InternalTree::ComboBoxText { attrs, .. } => ("ComboBoxText", None, attrs, &[]),
+        InternalTree::Slider { attrs, .. } => ("Slider", None, attrs, &[]),
+        InternalTree::Checkbox { attrs, .. } => ("Checkbox", None, attrs, &[]),
+        InternalTree::Calendar { attrs, .. } => ("Calendar", None, attrs, &[]),
+        InternalTree::ColorButton { attrs, .. } => ("ColorButton", None, attrs, &[]),
+        InternalTree::ColorChooser { attrs, .. } => ("ColorChooser", None, attrs, &[]),
+        InternalTree::CircularProgress { attrs, .. } => ("CircularProgress", None, attrs, &[]),
+        InternalTree::Graph { attrs, .. } => ("Graph", None, attrs, &[]),
+        InternalTree::Transform { attrs, .. } => ("Transform", None, attrs, &[]),
+    };
+
+    out.push('<');
+    out.push_str(name);
+
+    // The name of a window is an attribute in xml
+    if let Some(window_name) = name_attr {
+        out.push_str(&format!(" name=\"{}\"", escape(window_name)));
+    }
+
+    for attr in attrs {
+        out.push_str(&format!(" {}=\"{}\"", attr.key, escape(&attr.value)));
+    }
+
+    if children.is_empty() {
+        out.push_str("/>\n");
+        return;
+    }
+
+    out.push_str(">\n");
+    for child in children {
+        write_tree(child, widgets, out);
+    }
+    out.push_str(&format!("</{}>\n", name));
+}
+
+/// Escape an attribute value. Line breaks and tabs are escaped
+/// as well, as xml turns them into spaces otherwise.
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\n', "&#10;")
+        .replace('\r', "&#13;")
+        .replace('\t', "&#9;")
+}
@@ -0,0 +1,226 @@
+use super::{Attr, InternalTree};
+use crate::error::DiagInfo;
+
+/// Properties of `defwindow` that are written as a form, from
+/// the prefix of their attributes to the name of the form.
+const WINDOW_FORMS: [(&str, &str); 2] = [("geometry", "geometry"), ("reserve", "struts")];
+
+/// Generate an eww yuck config from the tree.
+/// Custom widgets are used as they are, so they should
+/// be defined with `defwidget` in another yuck file.
+pub fn internal_tree_to_yuck(tree: &InternalTree) -> Result<String, DiagInfo<'static>> {
+    let mut forms = Vec::new();
+
+    match tree {
+        InternalTree::Enter { children, .. } => {
+            for child in children {
+                forms.push(write_form(child, true, 0)?);
+            }
+        }
+        tree => forms.push(write_form(tree, true, 0)?),
+    }
+
+    Ok(format!("{}\n", forms.join("\n\n")))
+}
+
+/// Write a single form, with its children indented below it.
+fn write_form(
+    tree: &InternalTree,
+    is_root: bool,
+    indent: usize,
+) -> Result<String, DiagInfo<'static>> {
+    let inner_indent = "  ".repeat(indent + 1);
+
+    let (name, attrs, children): (&str, &[Attr], &[InternalTree]) = match tree {
+        InternalTree::Enter { children, .. } => ("enter", &[], children),
+
+        InternalTree::DefWindow { name, attrs, node, .. } => {
+            let mut window = format!("(defwindow {}", name);
+
+            for prop in window_props(attrs) {
+                window.push_str(&format!("\n{}{}", inner_indent, prop));
+            }
+
+            let node = write_form(node, false, indent + 1)?;
+            window.push_str(&format!("\n{}{})", inner_indent, node));
+            return Ok(window);
+        }
+
+        InternalTree::Poll { var, attrs, span } | InternalTree::Listen { var, attrs, span } => {
+            let (form, element) = match tree {
+                InternalTree::Poll { .. } => ("defpoll", "poll"),
+                _ => ("deflisten", "listen"),
+            };
+
+            if !is_root {
+                return Err(DiagInfo {
+                    code: "TE04",
+                    message: format!("Orphan {} element found deep inside root", element),
+                    label: Some("here"),
+                    note: Some("poll/listen elements should only be defined at the top of <Root>"),
+                    span: span.to_range(),
+                });
+            }
+
+            // The name is the variable, and the command comes last
+            let props = attrs.iter().filter(|a| a.key != "name" && a.key != "cmd");
+            let mut var = format!("({} {}{}", form, var, format_props(props));
+
+            if let Some(cmd) = attrs.iter().find(|a| a.key == "cmd") {
+                var.push_str(&format!(" {}", format_value(&cmd.value)));
+            }
+
+            var.push(')');
+            return Ok(var);
+        }
+
+        InternalTree::Custom { function, attrs, children, .. } => (function, attrs, children),
+
+        // A button has its label as its child in eww
+        InternalTree::Button { attrs, .. } => {
+            let props = attrs.iter().filter(|a| a.key != "label");
+            let mut button = format!("(button{}", format_props(props));
+
+            if let Some(label) = attrs.iter().find(|a| a.key == "label") {
+                button.push_str(&format!(" {}", format_value(&label.value)));
+            }
+
+            button.push(')');
+            return Ok(button);
+        }
+
+        InternalTree::Box { attrs, children, .. } => ("box", attrs, children),
+        InternalTree::CenterBox { attrs, children, .. } => ("centerbox", attrs, children),
+        InternalTree::Expander { attrs, children, .. } => ("expander", attrs, children),
+        InternalTree::Revealer { attrs, children, .. } => ("revealer", attrs, children),
+        InternalTree::Scroll { attrs, children, .. } => ("scroll", attrs, children),
+        InternalTree::OverLay { attrs, children, .. } => ("overlay", attrs, children),
+        InternalTree::Stack { attrs, children, .. } => ("stack", attrs, children),
+        InternalTree::EventBox { attrs, children, .. } => ("eventbox", attrs, children),
+        InternalTree::ToolTip { attrs, children, .. } => ("tooltip", attrs, children),
+        InternalTree::Label { attrs, .. } => ("label", attrs, &[]),
+        InternalTree::Image { attrs, .. } => ("image", attrs, &[]),
+        InternalTree::Input { attrs, .. } => ("input", attrs, &[]),
+        InternalTree::Progress { attrs, .. } => ("progress", attrs, &[]),
+        InternalTree::ComboBoxText { attrs, .. } => ("combo-box-text", attrs, &[]),
+        InternalTree::Slider { attrs, .. } => ("scale", attrs, &[]),
+        InternalTree::Checkbox { attrs, .. } => ("checkbox", attrs, &[]),
+        InternalTree::Calendar { attrs, .. } => ("calendar", attrs, &[]),
+        InternalTree::ColorButton { attrs, .. } => ("color-button", attrs, &[]),
+        InternalTree::ColorChooser { attrs, .. } => ("color-chooser", attrs, &[]),
+        InternalTree::CircularProgress { attrs, .. } => ("circular-progress", attrs, &[]),
+        InternalTree::Graph { attrs, .. } => ("graph", attrs, &[]),
+        InternalTree::Transform { attrs, .. } => ("transform", attrs, &[]),
+    };
+
+    let mut widget = format!("({}{}", name, format_props(attrs.iter()));
+
+    for child in children {
+        widget.push_str(&format!("\n{}{}", inner_indent, write_form(child, false, indent + 1)?));
+    }
+
+    widget.push(')');
+    Ok(widget)
+}
+
+/// The properties of a window, with the geometry and reserve grouped into forms.
+fn window_props(attrs: &[Attr]) -> Vec<String> {
+    let mut props = Vec::new();
+    let mut forms: Vec<(&str, &str, Vec<String>)> = Vec::new();
+
+    'attrs: for attr in attrs {
+        for (prefix, form) in WINDOW_FORMS {
+            if let Some(key) = attr.key.strip_prefix(prefix).and_then(|k| k.strip_prefix('.')) {
+                let prop = format!(":{} {}", yuck_key(key), format_value(&attr.value));
+
+                match forms.iter_mut().find(|(p, _, _)| *p == prefix) {
+                    Some((_, _, form_props)) => form_props.push(prop),
+                    None => forms.push((prefix, form, vec![prop])),
+                }
+                continue 'attrs;
+            }
+        }
+
+        props.push(format!(":{} {}", yuck_key(&attr.key), format_value(&attr.value)));
+    }
+
+    for (prefix, form, form_props) in forms {
+        props.push(format!(":{} ({} {})", prefix, form, form_props.join(" ")));
+    }
+
+    props
+}
+
+fn format_props<'a>(attrs: impl Iterator<Item = &'a Attr<'a>>) -> String {
+    attrs.map(|a| format!(" :{} {}", yuck_key(&a.key), format_value(&a.value))).collect()
+}
+
+/// Properties are snake_case in ewwii, and kebab-case in eww.
+fn yuck_key(key: &str) -> String {
+    key.replace('_', "-")
+}
+
+/// Format an attribute value as a yuck value. Numbers, booleans and
+/// variables are written as they are, and other code as an expression.
+fn format_value(value: &str) -> String {
+    match value.strip_prefix("@no_quote(").and_then(|s| s.strip_suffix(")")) {
+        Some(code) if is_atom(code) => code.to_string(),
+        Some(code) => format!("{{{}}}", code),
+        None => {
+            format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"))
+        }
+    }
+}
+
+fn is_atom(code: &str) -> bool {
+    let is_name = code.chars().next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && code.chars().all(|c| c.is_alphanumeric() || c == '_');
+
+    is_name || code.parse::<f64>().is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{FileInfo, Target, TranspileOptions, transpile};
+
+    #[test]
+    fn yuck_golden() {
+        let script = concat!(
+            "<Root>\n",
+            "    <Poll name=\"time\" cmd=\"date '+%H:%M'\" interval=\"1s\" initial=\"\"/>\n",
+            "    <Listen name=\"music\" cmd=\"playerctl -F metadata title\"/>\n",
+            "    <Window name=\"bar\" geometry.width=\"100%\" geometry.anchor=\"top center\">\n",
+            "        <CenterBox orientation=\"h\">\n",
+            "            <Label text=\"@no_quote(time)\"/>\n",
+            "            <Box spacing=\"5\">\n",
+            "                <Button onclick=\"notify-send hi\" label=\"Hi\"/>\n",
+            "                <Label text=\"@no_quote(music)\"/>\n",
+            "            </Box>\n",
+            "            <Label text=\"right\"/>\n",
+            "        </CenterBox>\n",
+            "    </Window>\n",
+            "</Root>\n",
+        );
+
+        let expected = concat!(
+            "(defpoll time :interval \"1s\" :initial \"\" \"date '+%H:%M'\")\n",
+            "\n",
+            "(deflisten music \"playerctl -F metadata title\")\n",
+            "\n",
+            "(defwindow bar\n",
+            "  :geometry (geometry :width \"100%\" :anchor \"top center\")\n",
+            "  (centerbox :orientation \"h\"\n",
+            "    (label :text time)\n",
+            "    (box :spacing \"5\"\n",
+            "      (button :onclick \"notify-send hi\" \"Hi\")\n",
+            "      (label :text music))\n",
+            "    (label :text \"right\")))\n",
+        );
+
+        let options = TranspileOptions { target: Target::Yuck, ..Default::default() };
+        let generated = transpile(&FileInfo { file_path: "bar.xml", script }, &options).unwrap();
+
+        assert_eq!(generated.code, expected);
+        assert!(generated.modules.is_empty());
+    }
+}
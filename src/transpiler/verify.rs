@@ -0,0 +1,54 @@
+use crate::error::DiagInfo;
+use rhai::{Engine, Position};
+use std::ops::Range;
+
+/// Compile the generated Rhai script without running it.
+/// This catches invalid code before ewwii loads the script.
+///
+/// The span of the returned [`DiagInfo`] points into `code`, not the xml.
+pub fn verify_rhai(code: &str) -> Result<(), DiagInfo<'static>> {
+    let mut engine = Engine::new_raw();
+    // Configs nest widgets deeper than the default limits allow
+    engine.set_max_expr_depths(0, 0);
+
+    let err = match engine.compile(code) {
+        Ok(_) => return Ok(()),
+        Err(e) => e,
+    };
+
+    let span = position_span(code, err.position());
+
+    Err(DiagInfo {
+        code: "TE05",
+        message: format!("The generated Rhai code failed to compile: {}", err.err_type()),
+        label: Some("here"),
+        note: Some("This is usually caused by an invalid @no_quote value."),
+        span,
+    })
+}
+
+/// Span of the character at a _rhai_ position in `code`.
+pub fn position_span(code: &str, pos: Position) -> Option<Range<usize>> {
+    pos.line().map(|row| {
+        let line_start: usize = code.lines().take(row - 1).map(|l| l.len() + 1).sum();
+        let start = line_start + pos.position().unwrap_or(1).saturating_sub(1);
+        start..start + 1
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_templates_verify() {
+        let code = "fn w() { box(#{}, [ label(#{ \"text\": `${t}` }) ]) }";
+        assert!(verify_rhai(code).is_ok());
+    }
+
+    #[test]
+    fn invalid_code_fails() {
+        let err = verify_rhai("label(#{ \"text\": 1 + })").unwrap_err();
+        assert_eq!(err.code, "TE05");
+    }
+}
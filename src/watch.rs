@@ -0,0 +1,193 @@
+//! Watch mode.
+//!
+//! Retranspiles the watched xml files whenever they change.
+//! The parent directory of each file is watched instead of the file
+//! itself, because many editors save by replacing the file which
+//! would silently drop an inotify watch on the old one.
+//!
+//! Output is only written when a file transpiles successfully, so the
+//! last good `.rhai` file stays in place while the xml is broken.
+
+use crate::report::{Report, run_jobs};
+use crate::{OutputOptions, output_claims, save_build_cache, transpile_file};
+
+use log::{error, info};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+use xmlman::cache::BuildCache;
+use xmlman::inputs::{STDIN, input_root, is_glob, is_xml, relative_to, resolve_inputs};
+use xmlman::{InputFile, TranspileOptions};
+
+/// How long to wait for more events after a change, so that
+/// an editor writing a file in several steps triggers one rebuild.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// A directory or glob input whose files are being watched.
+struct WatchedRoot {
+    /// The root as it was given, used to build the paths of new files.
+    given: PathBuf,
+    /// The canonicalized root, to compare against event paths.
+    canonical: PathBuf,
+    /// Pattern that the files of a glob input must match, relative to the root.
+    pattern: Option<glob::Pattern>,
+}
+
+struct WatchedPaths {
+    /// Files that were given directly, from their canonical to their given path.
+    files: BTreeMap<PathBuf, PathBuf>,
+    roots: Vec<WatchedRoot>,
+}
+
+impl WatchedPaths {
+    /// The input file that a changed `path` belongs to, if it is being watched.
+    fn input_for(&self, path: &Path) -> Option<InputFile> {
+        if !is_xml(path) {
+            return None;
+        }
+
+        if let Some(given) = self.files.get(path) {
+            return Some(InputFile::from_file(given));
+        }
+
+        self.roots.iter().find_map(|root| {
+            let relative = path.strip_prefix(&root.canonical).ok()?;
+
+            if root.pattern.as_ref().is_some_and(|p| !p.matches_path(relative)) {
+                return None;
+            }
+
+            Some(InputFile {
+                path: root.given.join(relative),
+                relative: relative.to_path_buf(),
+                from_stdin: false,
+            })
+        })
+    }
+}
+
+pub fn watch(
+    inputs: &[String],
+    options: &TranspileOptions,
+    output: &OutputOptions,
+    cache: Option<&BuildCache>,
+    jobs: usize,
+) {
+    if inputs.iter().any(|i| i == STDIN) {
+        error!("stdin can not be watched.");
+        return;
+    }
+
+    // Transpile everything once so the output is up to date
+    // before waiting for changes.
+    let files = match resolve_inputs(inputs, None, options) {
+        Ok(files) => files,
+        Err(e) => {
+            let mut report = Report::default();
+            report.fail(output, None, e);
+            report.print();
+            return;
+        }
+    };
+
+    let claims = output_claims(&files, options);
+    run_jobs(&files, jobs, |file, report| {
+        transpile_file(file, options, output, cache, Some(&claims), report)
+    });
+    save_build_cache(cache, output);
+
+    let mut watched = WatchedPaths { files: BTreeMap::new(), roots: Vec::new() };
+
+    for input in inputs {
+        match input_root(input) {
+            Some(root) => {
+                let pattern = is_glob(input)
+                    .then(|| {
+                        glob::Pattern::new(&relative_to(Path::new(input), &root).to_string_lossy())
+                    })
+                    .and_then(|p| p.ok());
+
+                let canonical = fs::canonicalize(&root).unwrap_or_else(|_| root.clone());
+                watched.roots.push(WatchedRoot { given: root, canonical, pattern });
+            }
+            None => {
+                let canonical = fs::canonicalize(input).unwrap_or_else(|_| input.into());
+                watched.files.insert(canonical, input.into());
+            }
+        }
+    }
+
+    let (tx, rx) = mpsc::channel::<notify::Result<Event>>();
+    let mut watcher = match notify::recommended_watcher(tx) {
+        Ok(w) => w,
+        Err(e) => {
+            error!("Failed to start watching: {}", e);
+            return;
+        }
+    };
+
+    let parents: BTreeSet<&Path> = watched.files.keys().filter_map(|f| f.parent()).collect();
+    let targets = parents
+        .into_iter()
+        .map(|p| (p, RecursiveMode::NonRecursive))
+        .chain(watched.roots.iter().map(|r| (r.canonical.as_path(), RecursiveMode::Recursive)));
+
+    for (target, mode) in targets {
+        if let Err(e) = watcher.watch(target, mode) {
+            error!("Failed to watch '{}': {}", target.display(), e);
+            return;
+        }
+    }
+
+    info!("[-] Watching for changes. Press Ctrl-C to stop.");
+
+    while let Ok(event) = rx.recv() {
+        let mut changed = BTreeMap::new();
+        collect_changed(event, &watched, &mut changed);
+
+        while let Ok(event) = rx.recv_timeout(DEBOUNCE) {
+            collect_changed(event, &watched, &mut changed);
+        }
+
+        // The files may have been deleted after the events were sent.
+        let changed: Vec<InputFile> = changed
+            .into_iter()
+            .filter(|(path, _)| path.is_file())
+            .map(|(_, input)| input)
+            .collect();
+
+        run_jobs(&changed, jobs, |input, report| {
+            report.info(format!("[-] Change detected in '{}'", input.path.display()));
+            transpile_file(input, options, output, cache, Some(&claims), report)
+        });
+
+        save_build_cache(cache, output);
+    }
+}
+
+fn collect_changed(
+    event: notify::Result<Event>,
+    watched: &WatchedPaths,
+    changed: &mut BTreeMap<PathBuf, InputFile>,
+) {
+    let event = match event {
+        Ok(e) => e,
+        Err(e) => {
+            error!("Watch error: {}", e);
+            return;
+        }
+    };
+
+    if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+        return;
+    }
+
+    for path in event.paths {
+        if let Some(input) = watched.input_for(&path) {
+            changed.insert(path, input);
+        }
+    }
+}
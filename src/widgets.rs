@@ -0,0 +1,396 @@
+//! Registry of the built-in xml elements.
+//!
+//! The registry is what the language server completes and documents.
+//! It has to be kept in sync with the elements that are converted in
+//! `transpiler/ast_to_tree.rs`, `tree_to_rhai.rs` and `tree_to_xml.rs`,
+//! which the tests check by transpiling every element both ways.
+
+/// Where an element can be used, and what it can contain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WidgetKind {
+    /// The `<Root>` element that contains everything else.
+    Root,
+    /// Elements that are only allowed directly inside of `<Root>`.
+    TopLevel,
+    /// Widgets that contain other widgets.
+    Container,
+    /// Widgets without children.
+    Leaf,
+}
+
+/// [`WidgetInfo`] describes a built-in xml element.
+#[derive(Debug)]
+pub struct WidgetInfo {
+    /// Name of the xml element.
+    pub name: &'static str,
+    /// Rhai function that the element is transpiled to.
+    pub function: &'static str,
+    pub kind: WidgetKind,
+    pub description: &'static str,
+    /// Attributes of this element. Widgets also take [`COMMON_ATTRIBUTES`].
+    pub attributes: &'static [&'static str],
+}
+
+impl WidgetInfo {
+    /// Every attribute that the element takes.
+    pub fn all_attributes(&self) -> impl Iterator<Item = &'static str> {
+        let common: &[&str] = match self.kind {
+            WidgetKind::Container | WidgetKind::Leaf => COMMON_ATTRIBUTES,
+            WidgetKind::Root | WidgetKind::TopLevel => &[],
+        };
+
+        self.attributes.iter().chain(common).copied()
+    }
+
+    /// Markdown documentation of the element.
+    pub fn documentation(&self) -> String {
+        let mut doc =
+            format!("**`<{}>`** → `{}`\n\n{}", self.name, self.function, self.description);
+
+        let attributes: Vec<String> = self.all_attributes().map(|a| format!("`{}`", a)).collect();
+        if !attributes.is_empty() {
+            doc.push_str(&format!("\n\nAttributes: {}", attributes.join(", ")));
+        }
+
+        doc
+    }
+}
+
+/// Attributes that every widget takes.
+pub const COMMON_ATTRIBUTES: &[&str] = &[
+    "class", "valign", "halign", "vexpand", "hexpand", "width", "height", "active", "tooltip",
+    "visible", "style", "css",
+];
+
+pub const WIDGETS: &[WidgetInfo] = &[
+    WidgetInfo {
+        name: "Root",
+        function: "enter",
+        kind: WidgetKind::Root,
+        description: "The root of the configuration. Contains the windows, polls and listens.",
+        attributes: &[],
+    },
+    WidgetInfo {
+        name: "Window",
+        function: "defwindow",
+        kind: WidgetKind::TopLevel,
+        description: "Defines a window. Needs a `name` and exactly one child.",
+        attributes: &[
+            "name",
+            "monitor",
+            "windowtype",
+            "stacking",
+            "exclusive",
+            "focusable",
+            "wm_ignore",
+            "geometry.x",
+            "geometry.y",
+            "geometry.width",
+            "geometry.height",
+            "geometry.anchor",
+            "reserve.side",
+            "reserve.distance",
+        ],
+    },
+    WidgetInfo {
+        name: "Poll",
+        function: "poll",
+        kind: WidgetKind::TopLevel,
+        description: "A variable that is updated by running a command on an interval.",
+        attributes: &["name", "cmd", "interval", "initial"],
+    },
+    WidgetInfo {
+        name: "Listen",
+        function: "listen",
+        kind: WidgetKind::TopLevel,
+        description: "A variable that is updated with every line a command prints.",
+        attributes: &["name", "cmd", "initial"],
+    },
+    WidgetInfo {
+        name: "Box",
+        function: "box",
+        kind: WidgetKind::Container,
+        description: "Lays out its children in a row or a column.",
+        attributes: &["orientation", "spacing", "space_evenly"],
+    },
+    WidgetInfo {
+        name: "CenterBox",
+        function: "centerbox",
+        kind: WidgetKind::Container,
+        description: "Lays out exactly three children, keeping the middle one centered.",
+        attributes: &["orientation"],
+    },
+    WidgetInfo {
+        name: "EventBox",
+        function: "eventbox",
+        kind: WidgetKind::Container,
+        description: "Runs commands on clicks, scrolls and hovers over its child.",
+        attributes: &[
+            "onclick",
+            "onmiddleclick",
+            "onrightclick",
+            "onscroll",
+            "onhover",
+            "onhoverlost",
+            "ondropped",
+            "dragvalue",
+            "dragtype",
+            "cursor",
+            "timeout",
+        ],
+    },
+    WidgetInfo {
+        name: "OverLay",
+        function: "overlay",
+        kind: WidgetKind::Container,
+        description: "Stacks its children on top of each other.",
+        attributes: &[],
+    },
+    WidgetInfo {
+        name: "Stack",
+        function: "stack",
+        kind: WidgetKind::Container,
+        description: "Shows one of its children at a time.",
+        attributes: &["selected", "transition", "same_size"],
+    },
+    WidgetInfo {
+        name: "Expander",
+        function: "expander",
+        kind: WidgetKind::Container,
+        description: "A container that can be expanded to show its children.",
+        attributes: &["name", "expanded"],
+    },
+    WidgetInfo {
+        name: "Revealer",
+        function: "revealer",
+        kind: WidgetKind::Container,
+        description: "Shows and hides its child with a transition.",
+        attributes: &["reveal", "transition", "duration"],
+    },
+    WidgetInfo {
+        name: "Scroll",
+        function: "scroll",
+        kind: WidgetKind::Container,
+        description: "A scrollable container.",
+        attributes: &["hscroll", "vscroll"],
+    },
+    WidgetInfo {
+        name: "ToolTip",
+        function: "tooltip",
+        kind: WidgetKind::Container,
+        description: "Shows its first child as the tooltip of its second child.",
+        attributes: &[],
+    },
+    WidgetInfo {
+        name: "Label",
+        function: "label",
+        kind: WidgetKind::Leaf,
+        description: "Displays text.",
+        attributes: &[
+            "text",
+            "markup",
+            "truncate",
+            "limit_width",
+            "show_truncated",
+            "wrap",
+            "angle",
+            "justify",
+            "xalign",
+            "yalign",
+            "unindent",
+        ],
+    },
+    WidgetInfo {
+        name: "Button",
+        function: "button",
+        kind: WidgetKind::Leaf,
+        description: "A button that runs a command when it is clicked.",
+        attributes: &["label", "onclick", "onmiddleclick", "onrightclick", "timeout"],
+    },
+    WidgetInfo {
+        name: "Image",
+        function: "image",
+        kind: WidgetKind::Leaf,
+        description: "Displays an image or an icon.",
+        attributes: &["path", "image_width", "image_height", "icon", "icon_size"],
+    },
+    WidgetInfo {
+        name: "Input",
+        function: "input",
+        kind: WidgetKind::Leaf,
+        description: "A text input.",
+        attributes: &["value", "onchange", "onaccept", "password", "timeout"],
+    },
+    WidgetInfo {
+        name: "Progress",
+        function: "progress",
+        kind: WidgetKind::Leaf,
+        description: "A progress bar.",
+        attributes: &["value", "orientation", "flipped"],
+    },
+    WidgetInfo {
+        name: "ComboBoxText",
+        function: "comboboxtext",
+        kind: WidgetKind::Leaf,
+        description: "A dropdown to pick one of a list of items.",
+        attributes: &["items", "onchange", "timeout"],
+    },
+    WidgetInfo {
+        name: "Slider",
+        function: "slider",
+        kind: WidgetKind::Leaf,
+        description: "A slider to pick a value in a range.",
+        attributes: &[
+            "value",
+            "min",
+            "max",
+            "orientation",
+            "flipped",
+            "marks",
+            "draw_value",
+            "value_pos",
+            "round_digits",
+            "onchange",
+            "timeout",
+        ],
+    },
+    WidgetInfo {
+        name: "Checkbox",
+        function: "checkbox",
+        kind: WidgetKind::Leaf,
+        description: "A checkbox that runs commands when it is checked or unchecked.",
+        attributes: &["checked", "onchecked", "onunchecked", "timeout"],
+    },
+    WidgetInfo {
+        name: "Calendar",
+        function: "calendar",
+        kind: WidgetKind::Leaf,
+        description: "A calendar.",
+        attributes: &[
+            "day",
+            "month",
+            "year",
+            "show_details",
+            "show_heading",
+            "show_day_names",
+            "show_week_numbers",
+            "onclick",
+            "timeout",
+        ],
+    },
+    WidgetInfo {
+        name: "ColorButton",
+        function: "colorbutton",
+        kind: WidgetKind::Leaf,
+        description: "A button that opens a color chooser.",
+        attributes: &["use_alpha", "onchange", "timeout"],
+    },
+    WidgetInfo {
+        name: "ColorChooser",
+        function: "colorchooser",
+        kind: WidgetKind::Leaf,
+        description: "A color chooser.",
+        attributes: &["use_alpha", "onchange", "timeout"],
+    },
+    WidgetInfo {
+        name: "CircularProgress",
+        function: "circularprogress",
+        kind: WidgetKind::Leaf,
+        description: "A progress bar in the shape of a circle.",
+        attributes: &["value", "start_at", "thickness", "clockwise"],
+    },
+    WidgetInfo {
+        name: "Graph",
+        function: "graph",
+        kind: WidgetKind::Leaf,
+        description: "Draws a graph of a value over time.",
+        attributes: &[
+            "value",
+            "thickness",
+            "time_range",
+            "min",
+            "max",
+            "dynamic",
+            "line_style",
+            "flip_x",
+            "flip_y",
+            "vertical",
+        ],
+    },
+    WidgetInfo {
+        name: "Transform",
+        function: "transform",
+        kind: WidgetKind::Leaf,
+        description: "Rotates, moves and scales what it draws.",
+        attributes: &[
+            "rotate",
+            "transform_origin_x",
+            "transform_origin_y",
+            "translate_x",
+            "translate_y",
+            "scale_x",
+            "scale_y",
+        ],
+    },
+];
+
+/// Look up a built-in element by its name.
+pub fn widget(name: &str) -> Option<&'static WidgetInfo> {
+    WIDGETS.iter().find(|w| w.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transpiler::convert_tree_to_xml;
+    use crate::{FileInfo, RhaiStyle, TranspileOptions, parse, to_internal_tree, to_rhai};
+    use std::collections::BTreeMap;
+
+    /// A config that uses the element once.
+    fn config(widget: &WidgetInfo) -> String {
+        let name = widget.name;
+        let window = |child: &str| format!("<Root><Window name=\"w\">{}</Window></Root>", child);
+
+        match widget.kind {
+            WidgetKind::Root => "<Root></Root>".to_string(),
+            WidgetKind::TopLevel if name == "Window" => window("<Label/>"),
+            WidgetKind::TopLevel => format!("<Root><{} name=\"v\"/></Root>", name),
+            WidgetKind::Container => window(&format!("<{0}><Label/></{0}>", name)),
+            WidgetKind::Leaf => window(&format!("<{}/>", name)),
+        }
+    }
+
+    /// Transpile the xml config to Rhai, and the tree back to xml.
+    fn transpile(xml: &str) -> (String, String) {
+        let options = TranspileOptions::default();
+        let file_info = FileInfo { file_path: "test.xml", script: xml };
+        let ast = parse(&file_info).unwrap();
+        let tree = to_internal_tree(&ast, &options).unwrap();
+
+        (
+            to_rhai(&tree, &RhaiStyle::default()).unwrap(),
+            convert_tree_to_xml(&tree, &BTreeMap::new()),
+        )
+    }
+
+    #[test]
+    fn widgets_round_trip() {
+        for widget in WIDGETS {
+            let (rhai, xml) = transpile(&config(widget));
+            let call = match widget.kind {
+                WidgetKind::Container => format!("{}(#{{}}, [", widget.function),
+                WidgetKind::Leaf => format!("{}(#{{}})", widget.function),
+                WidgetKind::Root | WidgetKind::TopLevel => format!("{}(", widget.function),
+            };
+
+            assert!(
+                rhai.lines().any(|l| l.trim_start().starts_with(&call)),
+                "{}:\n{}",
+                widget.name,
+                rhai
+            );
+            assert!(xml.contains(&format!("<{}", widget.name)), "{}:\n{}", widget.name, xml);
+            assert_eq!(transpile(&xml).0, rhai, "{}", widget.name);
+        }
+    }
+}